@@ -0,0 +1,159 @@
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// A point in time from a commit header or the command line
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Date {
+    /// Seconds since the Unix epoch
+    pub timestamp: i64,
+    /// Whether a time of day was given, rather than only the day
+    pub has_time: bool,
+}
+
+/// Days since 1970-01-01 of a day in the Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Count years from March so that the leap day is at the end of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parse `HH:MM` or `HH:MM:SS`, ignoring fractions of a second, into seconds
+/// since midnight
+fn parse_time(time: &str) -> Option<i64> {
+    let time = time.split_once('.').map_or(time, |(time, _)| time);
+    let mut parts = time.split(':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: i64 = parts.next().map_or(Some(0), |seconds| seconds.parse().ok())?;
+    if parts.next().is_some() || hours > 24 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Parse a timezone such as `+0100`, `-05:00` or `Z` into seconds east of UTC
+fn parse_zone(zone: &str) -> Option<i64> {
+    if zone == "Z" || zone.eq_ignore_ascii_case("utc") || zone.eq_ignore_ascii_case("gmt") {
+        return Some(0);
+    }
+    let sign = match zone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Split a time that has its timezone straight after it, as in the
+/// `10:00:00+01:00` of an ISO 8601 date
+fn split_time_and_zone(time: &str) -> (&str, Option<&str>) {
+    match time.find(['+', '-', 'Z']) {
+        Some(index) => (&time[..index], Some(&time[index..])),
+        None => (time, None),
+    }
+}
+
+/// Parse a date in any of the formats `git log --date` writes other than
+/// `relative`, such as `Mon Jan 15 10:00:00 2024 +0100`, `2024-01-15 10:00:00
+/// +0100`, `2024-01-15T10:00:00+01:00`, `Mon, 15 Jan 2024 10:00:00 +0100` and
+/// `1705309200 +0100`, or a shorter one such as `2024-01-15`, `20240115` or
+/// `@1705309200`. A date without a timezone is in UTC, and one without a time
+/// is at the start of the day.
+pub fn parse_date(date: &str) -> Option<Date> {
+    let date = date.trim();
+    // Seconds since the epoch need an `@`, or a timezone after them as in
+    // `--date=raw`, so that they can't be mistaken for a `YYYYMMDD` date
+    if let Some(seconds) = date.strip_prefix('@') {
+        let seconds = seconds.split_whitespace().next()?;
+        return Some(Date { timestamp: seconds.parse().ok()?, has_time: true });
+    }
+    if let Some((seconds, zone)) = date.split_once(' ') {
+        if seconds.len() > 4 && seconds.chars().all(|c| c.is_ascii_digit()) && parse_zone(zone).is_some() {
+            return Some(Date { timestamp: seconds.parse().ok()?, has_time: true });
+        }
+    }
+
+    let mut year: Option<i64> = None;
+    let mut month: Option<i64> = None;
+    let mut day: Option<i64> = None;
+    let mut time: Option<i64> = None;
+    let mut zone: i64 = 0;
+    for token in date.split([' ', ',']).filter(|token| !token.is_empty()) {
+        let is_iso_date = token.len() >= 10
+            && token.as_bytes()[4] == b'-'
+            && token.as_bytes()[7] == b'-'
+            && token.is_char_boundary(10);
+        if is_iso_date {
+            year = Some(token[..4].parse().ok()?);
+            month = Some(token[5..7].parse().ok()?);
+            day = Some(token[8..10].parse().ok()?);
+            if let Some(rest) = token[10..].strip_prefix('T') {
+                let (time_str, zone_str) = split_time_and_zone(rest);
+                time = Some(parse_time(time_str)?);
+                if let Some(zone_str) = zone_str {
+                    zone = parse_zone(zone_str)?;
+                }
+            } else if token.len() > 10 {
+                return None;
+            }
+        } else if token.contains(':') && !token.starts_with(['+', '-']) {
+            let (time_str, zone_str) = split_time_and_zone(token);
+            time = Some(parse_time(time_str)?);
+            if let Some(zone_str) = zone_str {
+                zone = parse_zone(zone_str)?;
+            }
+        } else if let Some(token_zone) = parse_zone(token) {
+            zone = token_zone;
+        } else if token.chars().all(|c| c.is_ascii_digit()) {
+            match token.len() {
+                8 => {
+                    year = Some(token[..4].parse().ok()?);
+                    month = Some(token[4..6].parse().ok()?);
+                    day = Some(token[6..].parse().ok()?);
+                }
+                4 => year = Some(token.parse().ok()?),
+                1 | 2 => day = Some(token.parse().ok()?),
+                _ => return None,
+            }
+        } else if token.chars().all(|c| c.is_ascii_alphabetic()) {
+            // Day names are left out since the date is enough
+            let name = token.to_ascii_lowercase();
+            if let Some(index) = MONTHS.iter().position(|month_name| name.starts_with(month_name)) {
+                month = Some(index as i64 + 1);
+            }
+        } else {
+            return None;
+        }
+    }
+
+    let (year, month, day) = (year?, month?, day?);
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    Some(Date {
+        timestamp: days_from_civil(year, month, day) * 86400 + time.unwrap_or(0) - zone,
+        has_time: time.is_some(),
+    })
+}
@@ -0,0 +1,193 @@
+/// The paths of a file before and after a change. A path is `None` when
+/// the file was created or deleted.
+#[derive(PartialEq, Debug, Default)]
+pub struct FilePaths {
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Undo the C-style quoting git uses for paths with unusual characters
+fn unquote(path: &str) -> String {
+    let Some(quoted) = path.strip_prefix('"').and_then(|path| path.strip_suffix('"')) else {
+        return path.to_string();
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut chars = quoted.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('a') => bytes.push(0x07),
+            Some('b') => bytes.push(0x08),
+            Some('f') => bytes.push(0x0c),
+            Some('r') => bytes.push(b'\r'),
+            Some('v') => bytes.push(0x0b),
+            // Non-ASCII bytes are written as 3 octal digits
+            Some(digit @ '0'..='7') => {
+                let mut value = digit.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    if let Some(digit) = chars.peek().and_then(|c| c.to_digit(8)) {
+                        value = value * 8 + digit;
+                        chars.next();
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(other) => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+            }
+            None => bytes.push(b'\\'),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Remove the `a/` or `b/` prefix from a path, or whichever prefix was given
+/// to `--src-prefix` and `--dst-prefix`
+fn strip_prefix(path: &str) -> Option<String> {
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = unquote(path);
+    match path.split_once('/') {
+        Some((_, rest)) => Some(rest.to_string()),
+        None => Some(path),
+    }
+}
+
+/// The path from a `---` or `+++` line. Anything after a tab is a timestamp,
+/// since git quotes paths that contain tabs. `diff -N` marks a file that
+/// doesn't exist on one side with the Unix epoch as its timestamp.
+fn marker_line_path(path: &str) -> Option<String> {
+    let (path, timestamp) = path.split_once('\t').unwrap_or((path, ""));
+    if is_epoch_timestamp(timestamp) {
+        return None;
+    }
+    strip_prefix(path)
+}
+
+/// Whether a `diff -u` timestamp such as `1970-01-01 01:00:00.000000000 +0100`
+/// is the Unix epoch. It is written in the local timezone, so it may be on the
+/// day before.
+fn is_epoch_timestamp(timestamp: &str) -> bool {
+    let parse = || -> Option<bool> {
+        let mut parts = timestamp.split(' ');
+        let day_offset = match parts.next()? {
+            "1970-01-01" => 0,
+            "1969-12-31" => -86400,
+            _ => return Some(false),
+        };
+        let time_and_fraction = parts.next()?;
+        let (time, fraction) = time_and_fraction.split_once('.').unwrap_or((time_and_fraction, "0"));
+        let mut time_parts = time.split(':').map(|part| part.parse::<i64>().ok());
+        let seconds = time_parts.next()?? * 3600 + time_parts.next()?? * 60 + time_parts.next()??;
+        let zone = parts.next()?;
+        let zone_sign = if zone.starts_with('-') { -1 } else { 1 };
+        let zone_hours: i64 = zone.get(1..3)?.parse().ok()?;
+        let zone_minutes: i64 = zone.get(3..5)?.parse().ok()?;
+        let zone_seconds = zone_sign * (zone_hours * 3600 + zone_minutes * 60);
+        Some(fraction.chars().all(|c| c == '0') && day_offset + seconds - zone_seconds == 0)
+    };
+    parse().unwrap_or(false)
+}
+
+/// `diff --git a/name b/name` is ambiguous when the names contain spaces, so
+/// this only handles the case where both names are the same, which is the
+/// only one where git doesn't also write `---`/`+++` or `rename` lines.
+fn diff_git_line_paths(rest: &str) -> FilePaths {
+    if let Some(quoted) = rest.strip_prefix('"') {
+        // The end of the quoted old path, including the closing quote
+        if let Some(end) = quoted.find("\" ").map(|index| index + 2) {
+            let old = strip_prefix(&rest[..end]);
+            let new = strip_prefix(&rest[end + 1..]);
+            return FilePaths { old, new };
+        }
+    }
+    let half = rest.len() / 2;
+    if rest.len() % 2 == 1 && rest.is_char_boundary(half) && rest.as_bytes()[half] == b' ' {
+        let old = strip_prefix(&rest[..half]);
+        let new = strip_prefix(&rest[half + 1..]);
+        if old == new {
+            return FilePaths { old, new };
+        }
+    }
+    match rest.split_once(" b/") {
+        Some((old, new)) => FilePaths {
+            old: strip_prefix(old),
+            new: Some(new.to_string()),
+        },
+        None => FilePaths::default(),
+    }
+}
+
+/// The paths in the `Binary files <old> and <new> differ` line that `diff -r`
+/// writes for binary files, without the `Binary files ` and ` differ`. The
+/// names aren't quoted, so this splits at the first ` and `.
+fn binary_files_line_paths(rest: &str) -> FilePaths {
+    match rest.split_once(" and ") {
+        Some((old, new)) => FilePaths { old: strip_prefix(old), new: strip_prefix(new) },
+        None => FilePaths::default(),
+    }
+}
+
+/// Find the paths in the lines of a file header, which have already had their
+/// ANSI codes and line endings removed
+pub fn parse_file_paths(lines: &[String]) -> FilePaths {
+    let mut paths = FilePaths::default();
+    let mut from_markers = FilePaths::default();
+    let mut has_markers = false;
+    let mut renamed = FilePaths::default();
+    let mut from_binary: Option<FilePaths> = None;
+    let mut is_new_file = false;
+    let mut is_deleted_file = false;
+    for line in lines {
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            paths = diff_git_line_paths(rest);
+        } else if let Some(path) = line.strip_prefix("diff --cc ").or(line.strip_prefix("diff --combined ")) {
+            // A combined diff of a merge names the file once, without a prefix
+            paths = FilePaths { old: Some(unquote(path)), new: Some(unquote(path)) };
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            from_markers.old = marker_line_path(path);
+            has_markers = true;
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            from_markers.new = marker_line_path(path);
+            has_markers = true;
+        } else if let Some(path) = line.strip_prefix("rename from ").or(line.strip_prefix("copy from ")) {
+            renamed.old = Some(unquote(path));
+        } else if let Some(path) = line.strip_prefix("rename to ").or(line.strip_prefix("copy to ")) {
+            renamed.new = Some(unquote(path));
+        } else if let Some(rest) = line.strip_prefix("Binary files ").and_then(|rest| rest.strip_suffix(" differ")) {
+            from_binary = Some(binary_files_line_paths(rest));
+        } else if line.starts_with("new file mode ") {
+            is_new_file = true;
+        } else if line.starts_with("deleted file mode ") {
+            is_deleted_file = true;
+        }
+    }
+    if has_markers {
+        return from_markers;
+    }
+    if renamed.old.is_some() || renamed.new.is_some() {
+        return renamed;
+    }
+    // git also writes the `Binary files` line, but after `diff --git`
+    if let Some(from_binary) = from_binary {
+        if paths == FilePaths::default() {
+            return from_binary;
+        }
+    }
+    // Empty files that are created or deleted have no `---`/`+++` lines
+    if is_new_file {
+        paths.old = None;
+    }
+    if is_deleted_file {
+        paths.new = None;
+    }
+    paths
+}
@@ -0,0 +1,59 @@
+use regex::Regex;
+use simple_error::{SimpleError, simple_error};
+
+use crate::{date::parse_date, parse_args::Config, patch_header::PatchHeader};
+
+/// The compiled form of the `--author`, `--committer`, `--since` and `--until`
+/// options in a `Config`
+pub struct HeaderFilter {
+    authors: Vec<Regex>,
+    committers: Vec<Regex>,
+    since: Option<i64>,
+    until: Option<i64>,
+}
+
+fn compile_regexes(patterns: &[String]) -> Result<Vec<Regex>, SimpleError> {
+    patterns.iter()
+        .map(|pattern| Regex::new(pattern).map_err(|err| simple_error!("Invalid regex '{}': {}", pattern, err)))
+        .collect()
+}
+
+/// Whether a name matches one of the regexes, if any were given. A patch
+/// without the name doesn't match any.
+fn matches_any(regexes: &[Regex], name: Option<&str>) -> bool {
+    regexes.is_empty() || name.is_some_and(|name| regexes.iter().any(|regex| regex.is_match(name)))
+}
+
+impl HeaderFilter {
+    pub fn new(config: &Config) -> Result<HeaderFilter, SimpleError> {
+        Ok(HeaderFilter {
+            authors: compile_regexes(&config.authors)?,
+            committers: compile_regexes(&config.committers)?,
+            since: config.since,
+            until: config.until,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.authors.is_empty() || !self.committers.is_empty() || self.since.is_some() || self.until.is_some()
+    }
+
+    /// Whether a commit takes part in matching. Like `git log --since`, dates
+    /// are compared with the commit date, but that is only shown by
+    /// `--format=fuller`, so otherwise the author date is used.
+    pub fn includes(&self, header: &PatchHeader) -> bool {
+        if !matches_any(&self.authors, header.author.as_deref())
+            || !matches_any(&self.committers, header.committer.as_deref())
+        {
+            return false;
+        }
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        let Some(date) = header.commit_date.as_ref().or(header.date.as_ref()).and_then(|date| parse_date(date)) else {
+            return false;
+        };
+        self.since.is_none_or(|since| date.timestamp >= since)
+            && self.until.is_none_or(|until| date.timestamp <= until)
+    }
+}
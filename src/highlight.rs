@@ -0,0 +1,114 @@
+use std::ops::Range;
+
+/// Bold red, the same as `grep --color`
+const HIGHLIGHT_STYLE: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[m";
+
+/// The length of the ANSI escape sequence at the start of `s`, if there is one
+fn escape_len(s: &str) -> Option<usize> {
+    let rest = s.strip_prefix('\x1b')?;
+    match rest.strip_prefix('[') {
+        // A CSI sequence ends at the first byte in the range '@' to '~'
+        Some(params) => params.bytes()
+            .position(|byte| (0x40..=0x7e).contains(&byte))
+            .map(|end| end + 3),
+        None => rest.chars().next().map(|c| 1 + c.len_utf8()),
+    }
+}
+
+/// Whether an escape sequence turns off all colors and styles
+fn is_reset(escape: &str) -> bool {
+    escape == "\x1b[m" || escape == "\x1b[0m"
+}
+
+/// Wrap the parts of a line that `find_matches` returns in the highlight
+/// style. `find_matches` is given the line without its ANSI codes and returns
+/// byte ranges into it. Colors that were already in the line are restored
+/// after each highlighted part, and changes of color within a highlighted part
+/// are overridden by the highlight.
+pub fn highlight_line(line: &str, find_matches: &dyn Fn(&str) -> Vec<Range<usize>>) -> String {
+    let mut segments: Vec<(bool, &str)> = Vec::new();
+    let mut text = String::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let (segment, is_escape) = match escape_len(rest) {
+            Some(len) if rest.is_char_boundary(len) => (&rest[..len], true),
+            _ => {
+                let len = match rest.find('\x1b') {
+                    // An escape character that doesn't start a whole sequence
+                    // is text on its own
+                    Some(0) => 1,
+                    Some(index) => index,
+                    None => rest.len(),
+                };
+                (&rest[..len], false)
+            }
+        };
+        if !is_escape {
+            text.push_str(segment);
+        }
+        segments.push((is_escape, segment));
+        rest = &rest[segment.len()..];
+    }
+    let mut found = find_matches(&text);
+    found.sort_by_key(|range| range.start);
+    // Matches of different patterns can overlap
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for range in found.into_iter().filter(|range| !range.is_empty()) {
+        match ranges.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => ranges.push(range),
+        }
+    }
+    if ranges.is_empty() {
+        return line.to_string();
+    }
+
+    let mut out = String::with_capacity(line.len());
+    // The escape sequences since the last reset, which are re-applied after
+    // each highlighted part
+    let mut active = String::new();
+    let mut ranges = ranges.iter().peekable();
+    let mut in_match = false;
+    let mut pos = 0;
+    for (is_escape, segment) in segments {
+        if is_escape {
+            out.push_str(segment);
+            if is_reset(segment) {
+                active.clear();
+            } else {
+                active.push_str(segment);
+            }
+            if in_match {
+                out.push_str(HIGHLIGHT_STYLE);
+            }
+            continue;
+        }
+        let end = pos + segment.len();
+        while pos < end {
+            let Some(range) = ranges.peek() else {
+                out.push_str(&text[pos..end]);
+                pos = end;
+                break;
+            };
+            if !in_match && pos == range.start {
+                out.push_str(HIGHLIGHT_STYLE);
+                in_match = true;
+            }
+            let boundary = if in_match { range.end } else { range.start }.min(end);
+            out.push_str(&text[pos..boundary]);
+            pos = boundary;
+            if in_match && pos == range.end {
+                out.push_str(RESET);
+                out.push_str(&active);
+                in_match = false;
+                ranges.next();
+            }
+        }
+    }
+    if in_match {
+        out.push_str(RESET);
+        out.push_str(&active);
+    }
+    out
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use simple_error::{SimpleError, simple_error};
+
+/// A line range from a `@@ -a,b +c,d @@` hunk header
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct HunkRange {
+    pub start: usize,
+    pub len: usize,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct HunkHeader {
+    /// The range in the old file, or in the first parent of a merge
+    pub old: HunkRange,
+    /// The ranges in the other parents of a merge, from the `@@@ -a,b -c,d +e,f @@@`
+    /// header of a combined diff. Empty for an ordinary diff.
+    pub other_parents: Vec<HunkRange>,
+    pub new: HunkRange,
+    /// The text after the closing `@@`, usually the enclosing function
+    pub section: String,
+}
+
+fn parse_range(range_str: &str) -> Option<HunkRange> {
+    // The length is omitted when it is 1
+    match range_str.split_once(',') {
+        Some((start, len)) => Some(HunkRange {
+            start: start.parse().ok()?,
+            len: len.parse().ok()?,
+        }),
+        None => Some(HunkRange {
+            start: range_str.parse().ok()?,
+            len: 1,
+        }),
+    }
+}
+
+/// The number of parents a hunk is a diff against, which is one less than the
+/// number of '@'s its header starts with. A combined diff of a merge has more
+/// than one, and each line of its body starts with a column per parent.
+pub fn hunk_parent_count(header: &str) -> usize {
+    header.chars().take_while(|c| *c == '@').count().saturating_sub(1).max(1)
+}
+
+/// Parse a hunk header with ANSI codes and the trailing newline already removed
+pub fn parse_hunk_header(header: &str) -> Result<HunkHeader, SimpleError> {
+    let invalid = || simple_error!("Invalid hunk header '{}'", header);
+    let marker = "@".repeat(hunk_parent_count(header) + 1);
+    let rest = header.strip_prefix(&marker).and_then(|rest| rest.strip_prefix(" -")).ok_or_else(invalid)?;
+    let (ranges, section) = rest.split_once(&format!(" {}", marker)).ok_or_else(invalid)?;
+    let (old_strs, new_str) = ranges.split_once(" +").ok_or_else(invalid)?;
+    let mut old_ranges = old_strs.split(" -")
+        .map(|old_str| parse_range(old_str).ok_or_else(invalid))
+        .collect::<Result<Vec<_>, _>>()?;
+    if old_ranges.len() != marker.len() - 1 {
+        return Err(invalid());
+    }
+    let old = old_ranges.remove(0);
+    Ok(HunkHeader {
+        old,
+        other_parents: old_ranges,
+        new: parse_range(new_str).ok_or_else(invalid)?,
+        section: section.to_string(),
+    })
+}
+
+fn format_range(range: &HunkRange) -> String {
+    if range.len == 1 {
+        range.start.to_string()
+    } else {
+        format!("{},{}", range.start, range.len)
+    }
+}
+
+/// Formats the header the same way git does, without a trailing newline
+impl fmt::Display for HunkHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let marker = "@".repeat(self.other_parents.len() + 2);
+        write!(f, "{} -{}", marker, format_range(&self.old))?;
+        for range in &self.other_parents {
+            write!(f, " -{}", format_range(range))?;
+        }
+        write!(f, " +{} {}{}", format_range(&self.new), marker, self.section)
+    }
+}
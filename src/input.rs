@@ -0,0 +1,67 @@
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufReader},
+    path::PathBuf,
+};
+use simple_error::simple_error;
+
+/// Where to read patches from
+pub enum InputSource {
+    Stdin,
+    File(PathBuf),
+}
+
+/// An open source of patches
+pub struct Input<'a> {
+    /// The name shown in errors, and in output when there is more than one
+    /// input. `None` when only reading stdin because no inputs were given.
+    pub name: Option<String>,
+    pub reader: Box<dyn io::BufRead + 'a>,
+}
+
+/// The sources to read for the paths given on the command line. `-` is stdin,
+/// and a directory is replaced by the `*.patch` files in it in order of their
+/// names, which is the order `git format-patch` numbers them in.
+pub fn input_sources(paths: &[String]) -> Result<Vec<InputSource>, Box<dyn Error>> {
+    let mut sources = Vec::new();
+    for path in paths {
+        if path == "-" {
+            sources.push(InputSource::Stdin);
+            continue;
+        }
+        let path_buf = PathBuf::from(path);
+        if !path_buf.is_dir() {
+            sources.push(InputSource::File(path_buf));
+            continue;
+        }
+        let mut patch_files: Vec<PathBuf> = fs::read_dir(&path_buf)
+            .map_err(|err| simple_error!("{}: {}", path, err))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()
+            .map_err(|err| simple_error!("{}: {}", path, err))?;
+        patch_files.retain(|file| file.is_file() && file.extension().is_some_and(|extension| extension == "patch"));
+        patch_files.sort();
+        sources.extend(patch_files.into_iter().map(InputSource::File));
+    }
+    Ok(sources)
+}
+
+impl InputSource {
+    pub fn open(&self) -> Result<Input<'static>, Box<dyn Error>> {
+        Ok(match self {
+            InputSource::Stdin => Input {
+                name: Some("(standard input)".to_string()),
+                reader: Box::new(io::stdin().lock()),
+            },
+            InputSource::File(path) => {
+                let name = path.display().to_string();
+                let file = fs::File::open(path).map_err(|err| simple_error!("{}: {}", name, err))?;
+                Input {
+                    name: Some(name),
+                    reader: Box::new(BufReader::new(file)),
+                }
+            }
+        })
+    }
+}
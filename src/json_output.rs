@@ -0,0 +1,190 @@
+use serde::Serialize;
+use simple_error::SimpleError;
+
+use crate::{
+    hunk_header::parse_hunk_header, matcher::Matcher,
+    numbered_lines::{line_selected, numbered_lines, LineKind}, parse_args::Config,
+    patch_header::parse_patch_header, strip_line, Patch, PatchMatches,
+};
+
+#[derive(Serialize)]
+pub struct JsonPatch {
+    /// The file the patch was read from, when there is more than one input
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input: Option<String>,
+    commit: Option<String>,
+    /// The branches and tags from `git log --decorate`, as git shows them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    refs: Vec<String>,
+    /// The abbreviated hashes from the `Merge:` line of a merge
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    parents: Vec<String>,
+    author: Option<String>,
+    date: Option<String>,
+    /// Only known for `git log --format=fuller`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    committer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit_date: Option<String>,
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    trailers: Vec<JsonTrailer>,
+    files: Vec<JsonFile>,
+}
+
+#[derive(Serialize)]
+struct JsonTrailer {
+    key: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct JsonFile {
+    /// `None` for a file that was created
+    old_path: Option<String>,
+    /// `None` for a file that was deleted
+    new_path: Option<String>,
+    /// The parent of a merge that the diff is against, when `git log -m`
+    /// shows a diff against each of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<String>,
+    hunks: Vec<JsonHunk>,
+}
+
+#[derive(Serialize)]
+struct JsonHunk {
+    old_start: usize,
+    old_lines: usize,
+    new_start: usize,
+    new_lines: usize,
+    /// The ranges in the other parents of a merge for a combined diff, where
+    /// the old range is the first parent's
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    other_parents: Vec<JsonRange>,
+    section: String,
+    /// Only the lines that matched, or with `-v` the ones that didn't
+    lines: Vec<JsonLine>,
+}
+
+#[derive(Serialize)]
+struct JsonRange {
+    start: usize,
+    lines: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonLineKind {
+    Added,
+    Removed,
+    Context,
+}
+
+impl From<LineKind> for JsonLineKind {
+    fn from(kind: LineKind) -> Self {
+        match kind {
+            LineKind::Added => JsonLineKind::Added,
+            LineKind::Removed => JsonLineKind::Removed,
+            LineKind::Context => JsonLineKind::Context,
+        }
+    }
+}
+
+/// How a line of a combined diff differs from one parent of the merge
+#[derive(Serialize)]
+struct JsonParentLine {
+    kind: JsonLineKind,
+    /// `None` if the line isn't in the parent
+    line: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct JsonLine {
+    kind: JsonLineKind,
+    /// `None` for an added line
+    old_line: Option<usize>,
+    /// `None` for a removed line
+    new_line: Option<usize>,
+    /// One entry per parent for a combined diff, and empty otherwise
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    parents: Vec<JsonParentLine>,
+    /// The line without its leading '+', '-' or ' '
+    content: String,
+}
+
+/// Build the JSON form of the files and hunks of a patch that are selected,
+/// the same ones that would be printed with `--print-fields`
+pub fn json_patch(
+    config: &Config,
+    matcher: &Matcher,
+    patch: &Patch,
+    matches: &PatchMatches,
+    input_name: Option<&str>
+) -> Result<JsonPatch, SimpleError> {
+    let header = patch.header();
+
+    let mut files = Vec::new();
+    for (file_index, file) in patch.files.iter().enumerate() {
+        if !matches.file_selected(&config.granularity, file_index) {
+            continue;
+        }
+        let paths = file.paths();
+        let parent_header_lines: Vec<String> = patch.parent_header(file.parent).lines.iter().map(|line| strip_line(line)).collect();
+        let parent = parse_patch_header(&parent_header_lines).parent;
+        let mut hunks = Vec::new();
+        for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+            if !matches.hunk_selected(&config.granularity, file_index, hunk_index) {
+                continue;
+            }
+            let hunk_header = parse_hunk_header(&strip_line(&hunk.header))?;
+            let lines = numbered_lines(hunk)?.into_iter()
+                .filter(|line| line_selected(config, matcher, matches, file_index, hunk_index, line))
+                .map(|line| JsonLine {
+                    kind: line.kind.into(),
+                    old_line: line.old_line,
+                    new_line: line.new_line,
+                    parents: if hunk.parents > 1 {
+                        line.parents.iter()
+                            .map(|parent| JsonParentLine { kind: parent.kind.into(), line: parent.line })
+                            .collect()
+                    } else {
+                        Vec::new()
+                    },
+                    content: line.content().to_string(),
+                })
+                .collect();
+            hunks.push(JsonHunk {
+                old_start: hunk_header.old.start,
+                old_lines: hunk_header.old.len,
+                new_start: hunk_header.new.start,
+                new_lines: hunk_header.new.len,
+                other_parents: hunk_header.other_parents.iter()
+                    .map(|range| JsonRange { start: range.start, lines: range.len })
+                    .collect(),
+                section: hunk_header.section.trim_start().to_string(),
+                lines,
+            });
+        }
+        files.push(JsonFile {
+            old_path: paths.old,
+            new_path: paths.new,
+            parent,
+            hunks,
+        });
+    }
+    Ok(JsonPatch {
+        input: input_name.map(str::to_string),
+        commit: header.commit,
+        refs: header.refs.iter().map(ToString::to_string).collect(),
+        parents: header.merge_parents,
+        author: header.author,
+        date: header.date,
+        committer: header.committer,
+        commit_date: header.commit_date,
+        message: header.message,
+        trailers: header.trailers.into_iter()
+            .map(|trailer| JsonTrailer { key: trailer.key, value: trailer.value })
+            .collect(),
+        files,
+    })
+}
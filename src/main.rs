@@ -1,10 +1,33 @@
+mod date;
+mod file_paths;
+mod header_filter;
+mod highlight;
+mod hunk_header;
+mod input;
+mod json_output;
+mod matcher;
+mod numbered_lines;
 mod parse_args;
+mod patch_header;
+mod path_filter;
+mod ref_filter;
 mod test;
 
 use console::strip_ansi_codes;
-use parse_args::{parse_program_args, UTF8Strategy, Config, OutputConfig};
+use file_paths::{parse_file_paths, FilePaths};
+use header_filter::HeaderFilter;
+use highlight::highlight_line;
+use hunk_header::{hunk_parent_count, parse_hunk_header, HunkHeader, HunkRange};
+use input::{input_sources, Input, InputSource};
+use json_output::json_patch;
+use matcher::Matcher;
+use numbered_lines::{body_line_kind, in_parent, line_selected, numbered_lines, LineKind};
+use patch_header::{format_refs, mbox_from_line_commit, parse_commit_line, parse_patch_header, PatchHeader};
+use path_filter::PathFilter;
+use ref_filter::RefFilter;
+use parse_args::{parse_program_args, UTF8Strategy, ColorChoice, Config, Granularity, MatchScope, OutputConfig, Pickaxe};
 use simple_error::{bail, SimpleError};
-use std::{error::Error, io};
+use std::{collections::HashSet, error::Error, io::{self, IsTerminal}, ops::Range};
 
 struct Chunk {
     lines: Vec<String>,
@@ -17,6 +40,9 @@ struct HunkDiffWithTail {
 
 struct Hunk {
     header: String,
+    /// The number of parents the hunk is a diff against, which is more than
+    /// one for a combined diff of a merge
+    parents: usize,
     context_head: Chunk,
     diffs: Vec<HunkDiffWithTail>,
 }
@@ -24,13 +50,60 @@ struct Hunk {
 struct FileDiff {
     file_header: Chunk,
     hunks: Vec<Hunk>,
+    /// Which of the patch's headers the diff follows. `git log -m` shows a
+    /// merge's diff against each of its parents after its own header.
+    parent: usize,
 }
 
 struct Patch {
     patch_header: Chunk,
+    /// The headers of a merge's diffs against its second and later parents
+    parent_headers: Vec<Chunk>,
     files: Vec<FileDiff>,
 }
 
+impl Patch {
+    fn new(patch_header: Chunk) -> Patch {
+        Patch {
+            patch_header,
+            parent_headers: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// The header that the files with the given `parent` follow
+    fn parent_header(&self, parent: usize) -> &Chunk {
+        match parent {
+            0 => &self.patch_header,
+            _ => &self.parent_headers[parent - 1],
+        }
+    }
+
+    /// Add a file whose header starts with `line`. It follows the most recent
+    /// of the patch's headers.
+    fn start_file(&mut self, line: String) {
+        self.files.push(FileDiff {
+            file_header: chunk_from(line),
+            hunks: Vec::new(),
+            parent: self.parent_headers.len(),
+        });
+    }
+
+    /// Parse the commit's header. `patch_header` keeps the lines it was
+    /// parsed from to print them as they were.
+    fn header(&self) -> PatchHeader {
+        let lines: Vec<String> = self.patch_header.lines.iter().map(|line| strip_line(line)).collect();
+        parse_patch_header(&lines)
+    }
+}
+
+impl FileDiff {
+    fn paths(&self) -> FilePaths {
+        let lines: Vec<String> = self.file_header.lines.iter().map(|line| strip_line(line)).collect();
+        parse_file_paths(&lines)
+    }
+}
+
 enum State {
     Start,
     PatchHeader,
@@ -38,6 +111,54 @@ enum State {
     HunkHead,
     HunkBodyDiff,
     HunkBodyTail,
+    /// The `-- ` line and git version after each patch from `git format-patch`
+    Signature,
+}
+
+/// The first line of a file's header, from `git diff`, `diff -ruN`, or the
+/// `Index:` lines that quilt and Subversion write
+fn is_file_start(line_stripped: &str) -> bool {
+    line_stripped.starts_with("diff ") || line_stripped.starts_with("Index: ")
+}
+
+/// The line that `diff -r` writes instead of a diff for binary files, and that
+/// git writes in the header of one
+fn is_binary_files_line(line_stripped: &str) -> bool {
+    line_stripped.starts_with("Binary files ") && line_stripped.ends_with(" differ")
+}
+
+/// The lines that `diff -r` writes about files and directories that aren't
+/// compared, which aren't part of any file's diff
+fn is_diff_r_note(line_stripped: &str) -> bool {
+    line_stripped.starts_with("Only in ") || line_stripped.starts_with("Common subdirectories: ")
+}
+
+/// The first line of a file's header after a commit's header, which is a
+/// combined diff for a merge
+fn is_git_file_start(line_stripped: &str) -> bool {
+    ["diff --git ", "diff --cc ", "diff --combined "].iter().any(|prefix| line_stripped.starts_with(prefix))
+}
+
+/// The first line of a `git log` commit or of a `git format-patch` email
+fn is_patch_start(line_stripped: &str) -> bool {
+    line_stripped.starts_with("commit ") || mbox_from_line_commit(line_stripped).is_some()
+}
+
+/// Whether a hunk body line was added or removed rather than context
+fn is_diff_line(line_stripped: &str, parents: usize) -> bool {
+    matches!(body_line_kind(line_stripped, parents), Some(LineKind::Added | LineKind::Removed))
+}
+
+/// Remove ANSI codes and the line ending from a line
+fn strip_line(line: &str) -> String {
+    let mut line_stripped = strip_ansi_codes(line).into_owned();
+    if line_stripped.ends_with('\n') {
+        line_stripped.pop();
+        if line_stripped.ends_with('\r') {
+            line_stripped.pop();
+        }
+    }
+    line_stripped
 }
 
 fn chunk_from(line: String) -> Chunk {
@@ -48,46 +169,537 @@ fn chunk_empty() -> Chunk {
     Chunk { lines: Vec::new() }
 }
 
+/// Which parts of a patch contain a match
+struct PatchMatches {
+    patch_header: bool,
+    files: Vec<FileMatches>,
+}
+
+struct FileMatches {
+    file_header: bool,
+    hunks: Vec<HunkMatches>,
+}
+
+struct HunkMatches {
+    /// The hunk header or any of its context lines matched
+    context: bool,
+    /// One entry per diff block in the hunk
+    diffs: Vec<bool>,
+}
+
+impl HunkMatches {
+    fn any(&self) -> bool {
+        self.context || self.diffs.iter().any(|matched| *matched)
+    }
+
+    fn clear(&mut self) {
+        self.context = false;
+        self.diffs.fill(false);
+    }
+}
+
+impl FileMatches {
+    fn any(&self) -> bool {
+        self.file_header || self.hunks.iter().any(HunkMatches::any)
+    }
+
+    fn clear(&mut self) {
+        self.file_header = false;
+        self.hunks.iter_mut().for_each(HunkMatches::clear);
+    }
+}
+
+impl PatchMatches {
+    fn any(&self) -> bool {
+        self.patch_header || self.files.iter().any(FileMatches::any)
+    }
+
+    fn clear(&mut self) {
+        self.patch_header = false;
+        self.files.iter_mut().for_each(FileMatches::clear);
+    }
+
+    /// A match in a header selects everything below it, so a file is printed
+    /// if the patch header or anything in the file matched.
+    fn file_selected(&self, granularity: &Granularity, file_index: usize) -> bool {
+        *granularity == Granularity::Patch
+            || self.patch_header
+            || self.files[file_index].any()
+    }
+
+    fn hunk_selected(&self, granularity: &Granularity, file_index: usize, hunk_index: usize) -> bool {
+        let file_matches = &self.files[file_index];
+        *granularity != Granularity::Hunk
+            || self.patch_header
+            || file_matches.file_header
+            || file_matches.hunks[hunk_index].any()
+    }
+
+    fn diff_selected(&self, file_index: usize, hunk_index: usize, diff_index: usize) -> bool {
+        self.context_selected(file_index, hunk_index)
+            || self.files[file_index].hunks[hunk_index].diffs[diff_index]
+    }
+
+    /// The context lines of a hunk are selected by a match in them, its
+    /// header or a header above it
+    fn context_selected(&self, file_index: usize, hunk_index: usize) -> bool {
+        let file_matches = &self.files[file_index];
+        self.patch_header
+            || file_matches.file_header
+            || file_matches.hunks[hunk_index].context
+    }
+}
+
+/// Print only the selected diff blocks as a patch that `git apply` accepts.
+/// Removals that were not selected become context and additions that were not
+/// selected are dropped, so the hunk headers are recomputed from what is kept.
+fn print_applyable_patch<'a>(
+    patch: &Patch,
+    matches: &PatchMatches,
+    writer: &mut Box<dyn io::Write + 'a>
+) -> Result<(), Box<dyn Error>> {
+    for line in &patch.patch_header.lines {
+        write!(writer, "{}", strip_ansi_codes(line))?;
+    }
+    let mut printed_parent = 0;
+    for (file_index, file) in patch.files.iter().enumerate() {
+        let file_header: Vec<String> = file.file_header.lines.iter()
+            .map(|line| strip_ansi_codes(line).into_owned())
+            .collect();
+        // Keeping only some of the removals would leave a deleted file with
+        // contents, so a deleted file is either removed entirely or skipped.
+        let is_deleted_file = file_header.iter().any(|line| line.trim_end() == "+++ /dev/null");
+        let file_matches = &matches.files[file_index];
+        // `git apply` can't apply the combined diff of a merge
+        if file.hunks.iter().any(|hunk| hunk.parents > 1) {
+            continue;
+        }
+
+        let mut hunks_out = String::new();
+        // How far the new side of the output has shifted from the old side
+        let mut line_delta: i64 = 0;
+        for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+            let selected: Vec<bool> = (0..hunk.diffs.len())
+                .map(|diff_index| {
+                    (is_deleted_file && file_matches.any())
+                        || matches.diff_selected(file_index, hunk_index, diff_index)
+                })
+                .collect();
+            if !selected.iter().any(|selected| *selected) {
+                continue;
+            }
+
+            let header = parse_hunk_header(&strip_line(&hunk.header))?;
+            let lines = hunk.context_head.lines.iter().map(|line| (line, true))
+                .chain(hunk.diffs.iter().zip(&selected).flat_map(|(diff, selected)| {
+                    diff.diff.lines.iter().map(move |line| (line, *selected))
+                        .chain(diff.context_tail.lines.iter().map(|line| (line, true)))
+                }));
+            let mut body = String::new();
+            let mut old_len = 0;
+            let mut new_len = 0;
+            let mut kept_previous = true;
+            for (line, selected) in lines {
+                let line = strip_ansi_codes(line);
+                if line.starts_with('\\') {
+                    // "\ No newline at end of file" belongs to the line before it
+                    if kept_previous {
+                        body.push_str(&line);
+                    }
+                    continue;
+                }
+                kept_previous = true;
+                if line.starts_with('+') {
+                    if selected {
+                        new_len += 1;
+                        body.push_str(&line);
+                    } else {
+                        kept_previous = false;
+                    }
+                } else if let Some(removed) = line.strip_prefix('-') {
+                    old_len += 1;
+                    if selected {
+                        body.push_str(&line);
+                    } else {
+                        new_len += 1;
+                        body.push(' ');
+                        body.push_str(removed);
+                    }
+                } else {
+                    old_len += 1;
+                    new_len += 1;
+                    body.push_str(&line);
+                }
+            }
+
+            // An empty range starts at the line before it
+            let mut new_start = header.old.start as i64 + line_delta;
+            if old_len == 0 {
+                new_start += 1;
+            }
+            if new_len == 0 {
+                new_start -= 1;
+            }
+            line_delta += new_len as i64 - old_len as i64;
+            let new_header = HunkHeader {
+                old: HunkRange { start: header.old.start, len: old_len },
+                other_parents: Vec::new(),
+                new: HunkRange { start: new_start.max(0) as usize, len: new_len },
+                section: header.section,
+            };
+            hunks_out.push_str(&format!("{}\n", new_header));
+            hunks_out.push_str(&body);
+        }
+
+        let file_selected = matches.patch_header || file_matches.file_header;
+        if hunks_out.is_empty() && !(file.hunks.is_empty() && file_selected) {
+            continue;
+        }
+        if file.parent != printed_parent {
+            for line in &patch.parent_header(file.parent).lines {
+                write!(writer, "{}", strip_ansi_codes(line))?;
+            }
+            printed_parent = file.parent;
+        }
+        for line in &file_header {
+            write!(writer, "{}", line)?;
+        }
+        write!(writer, "{}", hunks_out)?;
+    }
+    Ok(())
+}
+
+/// Write a line as it was read. With a matcher, the matches in the line are
+/// highlighted, in which case `body_columns` is how many '+', '-' or ' '
+/// columns a hunk body line starts with, and 0 for any other line.
+fn write_line<'a>(
+    writer: &mut Box<dyn io::Write + 'a>,
+    highlighter: Option<&Matcher>,
+    line: &str,
+    body_columns: usize
+) -> io::Result<()> {
+    let Some(matcher) = highlighter else {
+        return write!(writer, "{}", line);
+    };
+    let find_matches = |text: &str| -> Vec<Range<usize>> {
+        let text = text.trim_end_matches(['\n', '\r']);
+        let offset = text.len() - matcher.searched_part(text, body_columns).len();
+        matcher.positive_match_ranges(&text[offset..]).into_iter()
+            .map(|range| range.start + offset..range.end + offset)
+            .collect()
+    };
+    write!(writer, "{}", highlight_line(line, &find_matches))
+}
+
+/// Print each selected line on its own with its path and line number, either
+/// as `commit:path:line:content` or in the `path:line:column: message` form
+/// that editors read as a list of locations
+fn print_numbered_lines<'a>(
+    config: &Config,
+    matcher: &Matcher,
+    patch: &Patch,
+    matches: &PatchMatches,
+    print_state: &PrintState,
+    writer: &mut Box<dyn io::Write + 'a>
+) -> Result<(), Box<dyn Error>> {
+    let commit = patch.header().commit;
+    for (file_index, file) in patch.files.iter().enumerate() {
+        if !matches.file_selected(&config.granularity, file_index) {
+            continue;
+        }
+        let paths = file.paths();
+        for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+            if !matches.hunk_selected(&config.granularity, file_index, hunk_index) {
+                continue;
+            }
+            for line in numbered_lines(hunk)? {
+                if !line_selected(config, matcher, matches, file_index, hunk_index, &line) {
+                    continue;
+                }
+                // Removed lines are numbered in the old file
+                let path = match line.kind {
+                    LineKind::Removed => paths.old.as_ref().or(paths.new.as_ref()),
+                    _ => paths.new.as_ref().or(paths.old.as_ref()),
+                };
+                let path = path.map_or("", String::as_str);
+                if let OutputConfig::Quickfix = config.output {
+                    // Columns count bytes from 1, as in Vim. Lines selected by
+                    // -v have no match, so they point at the start of the line.
+                    let searched = matcher.searched_part(&line.text, hunk.parents);
+                    let columns = line.text.len() - line.content().len();
+                    let offset = line.text.len() - searched.len();
+                    let column = matcher.positive_match_ranges(searched).iter()
+                        .map(|range| (range.start + offset).saturating_sub(columns) + 1)
+                        .min()
+                        .unwrap_or(1);
+                    write!(writer, "{}:{}:{}: ", path, line.line_number(), column)?;
+                    if let Some(input_name) = &print_state.input_name {
+                        write!(writer, "{} ", input_name)?;
+                    }
+                    if let Some(commit) = &commit {
+                        write!(writer, "{} ", commit)?;
+                    }
+                    writeln!(writer, "{}", line.content().trim_start())?;
+                } else {
+                    if let Some(input_name) = &print_state.input_name {
+                        write!(writer, "{}:", input_name)?;
+                    }
+                    if let Some(commit) = &commit {
+                        write!(writer, "{}:", commit)?;
+                    }
+                    writeln!(writer, "{}:{}:{}", path, line.line_number(), line.content())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default, Clone, Copy)]
+struct LineCounts {
+    added: usize,
+    removed: usize,
+    context: usize,
+}
+
+impl LineCounts {
+    fn add(&mut self, other: LineCounts) {
+        self.added += other.added;
+        self.removed += other.removed;
+        self.context += other.context;
+    }
+}
+
+/// Print how many of the selected lines were added, removed and context, for
+/// the whole patch or with `--granularity file` or `hunk` for each file
+fn print_counts<'a>(
+    config: &Config,
+    matcher: &Matcher,
+    patch: &Patch,
+    matches: &PatchMatches,
+    print_state: &PrintState,
+    writer: &mut Box<dyn io::Write + 'a>
+) -> Result<(), Box<dyn Error>> {
+    let header = patch.header();
+    let per_file = config.granularity != Granularity::Patch;
+    let write_counts = |writer: &mut Box<dyn io::Write + 'a>, path: Option<&str>, counts: LineCounts| -> io::Result<()> {
+        if let Some(input_name) = &print_state.input_name {
+            write!(writer, "{}\t", input_name)?;
+        }
+        if let Some(commit) = &header.commit {
+            write!(writer, "{}\t", commit)?;
+            if config.show_refs {
+                write!(writer, "{}\t", format_refs(&header.refs))?;
+            }
+        }
+        if let Some(path) = path {
+            write!(writer, "{}\t", path)?;
+        }
+        writeln!(writer, "{}\t{}\t{}", counts.added, counts.removed, counts.context)
+    };
+    let mut patch_counts = LineCounts::default();
+    for (file_index, file) in patch.files.iter().enumerate() {
+        if !matches.file_selected(&config.granularity, file_index) {
+            continue;
+        }
+        let mut file_counts = LineCounts::default();
+        for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+            if !matches.hunk_selected(&config.granularity, file_index, hunk_index) {
+                continue;
+            }
+            for line in numbered_lines(hunk)? {
+                if !line_selected(config, matcher, matches, file_index, hunk_index, &line) {
+                    continue;
+                }
+                match line.kind {
+                    LineKind::Added => file_counts.added += 1,
+                    LineKind::Removed => file_counts.removed += 1,
+                    LineKind::Context => file_counts.context += 1,
+                }
+            }
+        }
+        if per_file {
+            let paths = file.paths();
+            let path = paths.new.as_ref().or(paths.old.as_ref()).map_or("", String::as_str);
+            write_counts(writer, Some(path), file_counts)?;
+        }
+        patch_counts.add(file_counts);
+    }
+    if !per_file {
+        write_counts(writer, None, patch_counts)?;
+    }
+    Ok(())
+}
+
+/// What has been printed so far, for output that spans more than one patch
+#[derive(Default)]
+struct PrintState {
+    /// The name of the input being read, if it is shown in the output
+    input_name: Option<String>,
+    /// Whether the `==> name <==` line that comes before the patches from the
+    /// current input has been printed
+    input_name_printed: bool,
+    patches_printed: usize,
+    /// The paths printed so far with `--unique`
+    printed_paths: HashSet<String>,
+}
+
+/// The path of each file in a patch, and whether it was selected. A merge
+/// from `git log -m` has a diff against each parent for the same file, which
+/// is selected if any of them are.
+fn selected_paths(patch: &Patch, matches: &PatchMatches) -> Vec<(String, bool)> {
+    let mut paths: Vec<(String, bool)> = Vec::new();
+    for (file_index, file) in patch.files.iter().enumerate() {
+        let file_paths = file.paths();
+        let path = file_paths.new.or(file_paths.old).unwrap_or_default();
+        let selected = matches.file_selected(&Granularity::File, file_index);
+        match paths.iter_mut().find(|(seen_path, _)| *seen_path == path) {
+            Some((_, seen_selected)) => *seen_selected |= selected,
+            None => paths.push((path, selected)),
+        }
+    }
+    paths
+}
+
+/// Print the path of each file that matched, or with `--files-without-match`
+/// each file that didn't
+fn print_file_names<'a>(
+    config: &Config,
+    patch: &Patch,
+    matches: &PatchMatches,
+    print_state: &mut PrintState,
+    writer: &mut Box<dyn io::Write + 'a>
+) -> Result<(), Box<dyn Error>> {
+    let header = patch.header();
+    let with_matches = matches!(config.output, OutputConfig::FilesWithMatches);
+    for (path, selected) in selected_paths(patch, matches) {
+        if selected != with_matches {
+            continue;
+        }
+        if config.unique {
+            if print_state.printed_paths.insert(path.clone()) {
+                writeln!(writer, "{}", path)?;
+            }
+            continue;
+        }
+        if let Some(input_name) = &print_state.input_name {
+            write!(writer, "{}\t", input_name)?;
+        }
+        if let Some(commit) = &header.commit {
+            write!(writer, "{}\t", commit)?;
+            if config.show_refs {
+                write!(writer, "{}\t", format_refs(&header.refs))?;
+            }
+        }
+        writeln!(writer, "{}", path)?;
+    }
+    Ok(())
+}
+
 fn print_patch<'a>(
-    output_config: &OutputConfig,
+    config: &Config,
+    matcher: &Matcher,
     patch: &Patch,
+    matches: &PatchMatches,
+    print_state: &mut PrintState,
     writer: &mut Box<dyn io::Write + 'a>
-) -> std::io::Result<()> {
-    match output_config {
+) -> Result<(), Box<dyn Error>> {
+    match &config.output {
+        OutputConfig::Patch => print_applyable_patch(patch, matches, writer)?,
+        OutputConfig::Json => {
+            // The patches are elements of one array, which process_lines
+            // opens and closes
+            if print_state.patches_printed > 0 {
+                writeln!(writer, ",")?;
+            }
+            // As an io::Error, a reader that closed the pipe is recognized
+            serde_json::to_writer_pretty(&mut *writer, &json_patch(config, matcher, patch, matches, print_state.input_name.as_deref())?).map_err(io::Error::from)?;
+        },
+        OutputConfig::FilesWithMatches | OutputConfig::FilesWithoutMatch => {
+            print_file_names(config, patch, matches, print_state, writer)?
+        },
+        OutputConfig::Count => print_counts(config, matcher, patch, matches, print_state, writer)?,
+        OutputConfig::Lines | OutputConfig::Quickfix => print_numbered_lines(config, matcher, patch, matches, print_state, writer)?,
+        OutputConfig::JsonLines => {
+            serde_json::to_writer(&mut *writer, &json_patch(config, matcher, patch, matches, print_state.input_name.as_deref())?).map_err(io::Error::from)?;
+            writeln!(writer)?;
+        },
         OutputConfig::CommitHash => {
-            let commit_line = strip_ansi_codes(&patch.patch_header.lines[0]);
-            let commit_hash = commit_line.strip_prefix("commit ")
-                        .expect("invalid commit message line").to_string();
-            write!(writer, "{}", commit_hash)?
+            // Plain `git diff` output has no commit header, so there is no
+            // hash to print for it.
+            let header = patch.header();
+            if let Some(commit) = &header.commit {
+                if let Some(input_name) = &print_state.input_name {
+                    write!(writer, "{}:", input_name)?;
+                }
+                if config.show_refs {
+                    writeln!(writer, "{}\t{}", commit, format_refs(&header.refs))?
+                } else {
+                    writeln!(writer, "{}", commit)?
+                }
+            }
         },
         OutputConfig::Sections(print_sections) => {
+            // Patches can't have a prefix on each line like the other outputs
+            // do, so name the input once before its first patch, like `head`
+            if let Some(input_name) = &print_state.input_name {
+                if !print_state.input_name_printed {
+                    writeln!(writer, "==> {} <==", input_name)?;
+                    print_state.input_name_printed = true;
+                }
+            }
+            // Only the fields that were searched are highlighted
+            let highlighter = |searched: bool| -> Option<&Matcher> {
+                (config.highlight && config.color == ColorChoice::Always && searched).then_some(matcher)
+            };
             if print_sections.patch_header {
                 for line in &patch.patch_header.lines {
-                    write!(writer, "{}", line)?;
+                    write_line(writer, highlighter(config.match_on.patch_header), line, 0)?;
                 }
             }
-            for file in &patch.files {
+            let mut printed_parent = 0;
+            for (file_index, file) in patch.files.iter().enumerate() {
+                if !matches.file_selected(&config.granularity, file_index) {
+                    continue;
+                }
+                // The diffs against each parent of a merge follow their own
+                // copy of its header
+                if file.parent != printed_parent && print_sections.patch_header {
+                    for line in &patch.parent_header(file.parent).lines {
+                        write_line(writer, highlighter(config.match_on.patch_header), line, 0)?;
+                    }
+                }
+                printed_parent = file.parent;
                 if print_sections.file_header {
                     for line in &file.file_header.lines {
-                        write!(writer, "{}", line)?;
+                        write_line(writer, highlighter(config.match_on.file_header), line, 0)?;
                     }
                 }
-                for hunk in &file.hunks {
+                for (hunk_index, hunk) in file.hunks.iter().enumerate() {
+                    if !matches.hunk_selected(&config.granularity, file_index, hunk_index) {
+                        continue;
+                    }
                     if print_sections.context {
-                        write!(writer, "{}", hunk.header)?;
+                        write_line(writer, highlighter(config.match_on.context), &hunk.header, 0)?;
                         for line in &hunk.context_head.lines {
-                            write!(writer, "{}", line)?;
+                            write_line(writer, highlighter(config.match_on.context), line, hunk.parents)?;
                         }
                     }
                     for diff in &hunk.diffs {
-                        if print_sections.diff {
-                            for line in &diff.diff.lines {
-                                write!(writer, "{}", line)?;
+                        for line in &diff.diff.lines {
+                            let is_added = body_line_kind(&strip_line(line), hunk.parents) == Some(LineKind::Added);
+                            if (is_added && print_sections.added) || (!is_added && print_sections.removed) {
+                                let searched = if is_added {
+                                    config.match_on.added
+                                } else {
+                                    config.match_on.removed
+                                };
+                                write_line(writer, highlighter(searched), line, hunk.parents)?;
                             }
                         }
                         if print_sections.context {
                             for line in &diff.context_tail.lines {
-                                write!(writer, "{}", line)?;
+                                write_line(writer, highlighter(config.match_on.context), line, hunk.parents)?;
                             }
                         }
                     }
@@ -98,91 +710,381 @@ fn print_patch<'a>(
     Ok(())
 }
 
-fn process_patch<'a>(
+/// Hunk body lines are matched without their leading '+', '-' or ' ' so
+/// that anchored patterns apply to the content of the line. A combined diff
+/// has one of those per parent.
+fn body_line_content(line: &str, parents: usize) -> &str {
+    let mut chars = line.chars();
+    for _ in 0..parents {
+        chars.next();
+    }
+    chars.as_str()
+}
+
+/// Find the lines in the fields of a patch that `config.match_on` selects
+/// which satisfy `line_matches`
+fn match_lines(
     config: &Config,
+    matcher: &Matcher,
     patch: &Patch,
-    writer: &mut Box<dyn io::Write + 'a>
-) -> std::io::Result<()> {
-    let mut process_lines = |lines: &Vec<String>| -> std::io::Result<bool> {
-        for line in lines {
-            if line.contains(&config.search_string) {
-                print_patch(&config.output, patch, writer)?;
-                return Ok(true);
+    line_matches: &dyn Fn(&str) -> bool
+) -> PatchMatches {
+    let header_matches = |lines: &Vec<String>| -> bool {
+        lines.iter().any(|line| line_matches(&strip_line(line)))
+    };
+    let body_matches = |lines: &Vec<String>, parents: usize| -> bool {
+        lines.iter().any(|line| line_matches(matcher.searched_part(&strip_line(line), parents)))
+    };
+    let diff_matches = |lines: &Vec<String>, parents: usize| -> bool {
+        lines.iter().any(|line| {
+            let line_stripped = strip_line(line);
+            let match_on_line = match body_line_kind(&line_stripped, parents) {
+                Some(LineKind::Added) => config.match_on.added,
+                _ => config.match_on.removed,
+            };
+            match_on_line && line_matches(matcher.searched_part(&line_stripped, parents))
+        })
+    };
+
+    // With more than one pattern, a file matches if the number of
+    // occurrences of any one of them that isn't negated changed
+    let occurrence_count_changed = |file: &FileDiff| -> bool {
+        let mut added_counts = vec![0; matcher.pattern_count()];
+        let mut removed_counts = vec![0; matcher.pattern_count()];
+        for hunk in &file.hunks {
+            for line in hunk.diffs.iter().flat_map(|diff| &diff.diff.lines) {
+                let line_stripped = strip_line(line);
+                let counts = match body_line_kind(&line_stripped, hunk.parents) {
+                    Some(LineKind::Added) => &mut added_counts,
+                    _ => &mut removed_counts,
+                };
+                for (index, count) in counts.iter_mut().enumerate() {
+                    if !matcher.is_negated(index) {
+                        *count += matcher.count(index, matcher.searched_part(&line_stripped, hunk.parents));
+                    }
+                }
             }
         }
-        Ok(false)
+        added_counts != removed_counts
     };
 
-    if config.match_on.patch_header {
-        if process_lines(&patch.patch_header.lines)? {
-            return Ok(());
-        }
+    PatchMatches {
+        patch_header: config.match_on.patch_header && header_matches(&patch.patch_header.lines),
+        files: patch.files.iter().map(|file| {
+            let diffs_can_match = match config.pickaxe {
+                Pickaxe::LineMatch => true,
+                Pickaxe::OccurrenceCount => occurrence_count_changed(file),
+            };
+            FileMatches {
+                file_header: config.match_on.file_header && header_matches(&file.file_header.lines),
+                hunks: file.hunks.iter().map(|hunk| HunkMatches {
+                    context: config.match_on.context && (
+                        line_matches(&strip_line(&hunk.header))
+                        || body_matches(&hunk.context_head.lines, hunk.parents)
+                        || hunk.diffs.iter().any(|diff| body_matches(&diff.context_tail.lines, hunk.parents))
+                    ),
+                    diffs: hunk.diffs.iter()
+                        .map(|diff| diffs_can_match && diff_matches(&diff.diff.lines, hunk.parents))
+                        .collect(),
+                }).collect(),
+            }
+        }).collect(),
+    }
+}
+
+fn match_patch(config: &Config, matcher: &Matcher, patch: &Patch) -> PatchMatches {
+    if config.scope == MatchScope::Line {
+        return match_lines(config, matcher, patch, &|line| matcher.is_match(line));
     }
-    for file in &patch.files {
-        if config.match_on.file_header {
-            if process_lines(&file.file_header.lines)? {
-                return Ok(());
+
+    // Evaluate the expression once per hunk, file or patch using which
+    // patterns were found anywhere in it. The lines that are selected in a
+    // matching hunk, file or patch are the ones with a pattern that isn't
+    // negated, or all of it if there are none.
+    let pattern_matches: Vec<PatchMatches> = (0..matcher.pattern_count())
+        .map(|index| match_lines(config, matcher, patch, &|line| matcher.pattern_is_match(index, line)))
+        .collect();
+    let scope_matches = |scope_any: &dyn Fn(&PatchMatches) -> bool| -> bool {
+        let found: Vec<bool> = pattern_matches.iter().map(scope_any).collect();
+        matcher.eval(&found)
+    };
+    let mut matches = match_lines(config, matcher, patch, &|line| matcher.is_positive_match(line));
+    match config.scope {
+        MatchScope::Line => unreachable!(),
+        MatchScope::Hunk => {
+            matches.patch_header = false;
+            for (file_index, file_matches) in matches.files.iter_mut().enumerate() {
+                file_matches.file_header = false;
+                for (hunk_index, hunk_matches) in file_matches.hunks.iter_mut().enumerate() {
+                    if !scope_matches(&|m| m.files[file_index].hunks[hunk_index].any()) {
+                        hunk_matches.clear();
+                    } else if !hunk_matches.any() {
+                        hunk_matches.context = true;
+                    }
+                }
             }
         }
-        for hunk in &file.hunks {
-            if config.match_on.context && hunk.header.contains(&config.search_string) {
-                return Ok(print_patch(&config.output, patch, writer)?);
-            }
-            if config.match_on.context {
-                if process_lines(&hunk.context_head.lines)? {
-                    return Ok(());
+        MatchScope::File => {
+            matches.patch_header = false;
+            for (file_index, file_matches) in matches.files.iter_mut().enumerate() {
+                if !scope_matches(&|m| m.files[file_index].any()) {
+                    file_matches.clear();
+                } else if !file_matches.any() {
+                    file_matches.file_header = true;
                 }
             }
-            for diff in &hunk.diffs {
-                if config.match_on.diff {
-                    if process_lines(&diff.diff.lines)? {
-                        return Ok(());
-                    }
-                }
-                if config.match_on.context {
-                    if process_lines(&diff.context_tail.lines)? {
-                        return Ok(());
+        }
+        MatchScope::Patch => {
+            if !scope_matches(&PatchMatches::any) {
+                matches.clear();
+            } else if !matches.any() {
+                matches.patch_header = true;
+            }
+        }
+    }
+    matches
+}
+
+/// Select the files or hunks that did not match instead of the ones that
+/// did, at the same level that they are printed at
+fn invert_matches(config: &Config, matches: &PatchMatches) -> PatchMatches {
+    // --print-patch selects individual diff blocks, so those are inverted
+    let invert_diffs = matches!(config.output, OutputConfig::Patch);
+    PatchMatches {
+        patch_header: config.granularity == Granularity::Patch && !invert_diffs && !matches.any(),
+        files: matches.files.iter().map(|file_matches| {
+            let file_selected = matches.patch_header || file_matches.any();
+            FileMatches {
+                file_header: config.granularity == Granularity::File && !invert_diffs && !file_selected,
+                hunks: file_matches.hunks.iter().map(|hunk_matches| {
+                    let hunk_selected = matches.patch_header || file_matches.file_header || hunk_matches.any();
+                    HunkMatches {
+                        context: config.granularity == Granularity::Hunk && !invert_diffs && !hunk_selected,
+                        diffs: hunk_matches.diffs.iter().map(|diff_matched| {
+                            let diff_selected = matches.patch_header
+                                || file_matches.file_header
+                                || hunk_matches.context
+                                || *diff_matched;
+                            invert_diffs && !diff_selected
+                        }).collect(),
                     }
-                }
+                }).collect(),
             }
+        }).collect(),
+    }
+}
+
+/// Which patches and files take part in matching
+struct Filters {
+    paths: PathFilter,
+    refs: RefFilter,
+    header: HeaderFilter,
+}
+
+/// Match a patch and print it if it matched. Returns whether it matched.
+fn process_patch<'a>(
+    config: &Config,
+    matcher: &Matcher,
+    filters: &Filters,
+    patch: &mut Patch,
+    print_state: &mut PrintState,
+    writer: &mut Box<dyn io::Write + 'a>
+) -> Result<bool, Box<dyn Error>> {
+    if config.first_parent {
+        patch.files.retain(|file| file.parent == 0);
+        patch.parent_headers.clear();
+    }
+    if filters.refs.is_active() || filters.header.is_active() {
+        let header = patch.header();
+        if !filters.refs.includes(&header.refs) || !filters.header.includes(&header) {
+            return Ok(false);
         }
     }
-    Ok(())
+    if filters.paths.is_active() {
+        patch.files.retain(|file| filters.paths.includes(&file.paths()));
+        // A patch that only touched other files is left out entirely, even
+        // if its header matches
+        if patch.files.is_empty() {
+            return Ok(false);
+        }
+    }
+    let mut matches = match_patch(config, matcher, patch);
+    if config.invert_match {
+        matches = invert_matches(config, &matches);
+    }
+    // With --files-without-match, a patch counts if any of its files didn't
+    // match, even if nothing in the patch did
+    let matched = match config.output {
+        OutputConfig::FilesWithoutMatch => selected_paths(patch, &matches).iter().any(|(_, selected)| !selected),
+        _ => matches.any(),
+    };
+    if matched && !config.quiet {
+        print_patch(config, matcher, patch, &matches, print_state, writer)?;
+        print_state.patches_printed += 1;
+    }
+    Ok(matched)
+}
+
+/// Start a new patch at the `commit` line of the next patch, once the current
+/// one has been processed. Returns whether the current one matched. With
+/// `git log -m`, a merge's header is repeated before its diff against each
+/// parent, and those diffs are kept together in one patch.
+fn start_patch<'a>(
+    config: &Config,
+    matcher: &Matcher,
+    filters: &Filters,
+    patch: &mut Patch,
+    line: String,
+    print_state: &mut PrintState,
+    writer: &mut Box<dyn io::Write + 'a>
+) -> Result<bool, Box<dyn Error>> {
+    let next_commit_line = strip_line(&line);
+    let current_commit_line = patch.patch_header.lines.first().map(|line| strip_line(line)).unwrap_or_default();
+    let is_next_parent = match (parse_commit_line(&next_commit_line), parse_commit_line(&current_commit_line)) {
+        (Some(next), Some(current)) => next.parent.is_some() && next.hash == current.hash,
+        _ => false,
+    };
+    if is_next_parent {
+        patch.parent_headers.push(chunk_from(line));
+        return Ok(false);
+    }
+    let matched = process_patch(config, matcher, filters, patch, print_state, writer)?;
+    *patch = Patch::new(chunk_from(line));
+    Ok(matched)
 }
 
-fn main() -> () {
-    real_main().or_else(|err| -> Result<(), Box<dyn Error>> {
-        println!("{}", err);
-        std::process::exit(1)
-    }).expect("process::exit failed to exit");
+/// Like grep, exit with 0 if anything matched, 1 if nothing did, and 2 if
+/// there was an error
+fn main() {
+    let status = match real_main() {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        // --help returns an empty error once the help has been printed
+        Err(err) if err.to_string().is_empty() => 0,
+        // The reader stopped early, as `head` does, after something was
+        // printed, so there was a match
+        Err(err) if is_broken_pipe(&*err) => 0,
+        Err(err) => {
+            eprintln!("{}", err);
+            2
+        }
+    };
+    std::process::exit(status);
 }
 
-fn real_main() -> Result<(), Box<dyn Error>> {
-    let config = parse_program_args(&mut std::env::args())?;
-    Ok(
-        process_lines(
-            Box::new(io::stdin().lock()), 
-            Box::new(io::stdout().lock()), 
-            &config)?
+fn is_broken_pipe(err: &(dyn Error + 'static)) -> bool {
+    err.downcast_ref::<io::Error>().is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+}
+
+fn real_main() -> Result<bool, Box<dyn Error>> {
+    let mut config = parse_program_args(&mut std::env::args())?;
+    if config.color == ColorChoice::Auto {
+        config.color = if io::stdout().is_terminal() {
+            ColorChoice::Always
+        } else {
+            ColorChoice::Never
+        };
+    }
+    if config.inputs.is_empty() {
+        return process_lines(
+            Box::new(io::stdin().lock()),
+            Box::new(io::stdout().lock()),
+            &config
+        );
+    }
+    let sources = input_sources(&config.inputs)?;
+    // Like grep, only show which input the output came from if there is more
+    // than one
+    let show_input_names = sources.len() > 1;
+    process_inputs(
+        &mut sources.iter().map(InputSource::open),
+        show_input_names,
+        Box::new(io::stdout().lock()),
+        &config
     )
 }
 
+/// Read patches from `reader` and print the ones that match to `writer`.
+/// Returns whether anything matched.
 fn process_lines<'a>(
-        mut reader: Box<dyn io::BufRead + 'a>,
+        reader: Box<dyn io::BufRead + 'a>,
+        writer: Box<dyn io::Write + 'a>,
+        config: &Config
+) -> Result<bool, Box<dyn Error>> {
+    let input = Input { name: None, reader };
+    process_inputs(&mut std::iter::once(Ok(input)), false, writer, config)
+}
+
+/// Read patches from each input in turn, as if they were one long input.
+/// Errors are prefixed with the name of the input they happened in.
+fn process_inputs<'r, 'a>(
+        inputs: &mut dyn Iterator<Item = Result<Input<'r>, Box<dyn Error>>>,
+        show_input_names: bool,
         mut writer: Box<dyn io::Write + 'a>,
         config: &Config
-) -> Result<(), Box<dyn Error>> {
+) -> Result<bool, Box<dyn Error>> {
+    let matcher = Matcher::new(config)?;
+    let filters = Filters {
+        paths: PathFilter::new(config)?,
+        refs: RefFilter::new(config)?,
+        header: HeaderFilter::new(config)?,
+    };
+    let mut print_state = PrintState::default();
+    let mut matched = false;
+    let print_json_array = matches!(config.output, OutputConfig::Json) && !config.quiet;
+    if print_json_array {
+        writeln!(writer, "[")?;
+    }
+    for input in inputs {
+        let input = input?;
+        print_state.input_name = if show_input_names { input.name.clone() } else { None };
+        print_state.input_name_printed = false;
+        let result = process_input(input.reader, config, &matcher, &filters, &mut print_state, &mut writer);
+        matched |= match (result, input.name) {
+            (Ok(input_matched), _) => input_matched,
+            // Failing to write the output has nothing to do with the input
+            (Err(err), _) if is_broken_pipe(&*err) => return Err(err),
+            (Err(err), Some(name)) => bail!("{}: {}", name, err),
+            (Err(err), None) => return Err(err),
+        };
+        if matched && config.quiet {
+            return Ok(true);
+        }
+    }
+    if print_json_array {
+        if print_state.patches_printed > 0 {
+            writeln!(writer)?;
+        }
+        writeln!(writer, "]")?;
+    }
+    Ok(matched)
+}
+
+/// Returns whether anything in the input matched
+fn process_input<'r, 'a>(
+        mut reader: Box<dyn io::BufRead + 'r>,
+        config: &Config,
+        matcher: &Matcher,
+        filters: &Filters,
+        print_state: &mut PrintState,
+        writer: &mut Box<dyn io::Write + 'a>
+) -> Result<bool, Box<dyn Error>> {
     let mut line_num = 0;
     let mut state = State::Start;
     // store only 1 patch worth of context
-    let mut patch = Patch {
-        patch_header: Chunk { lines: Vec::new() },
-        files: Vec::new(),
-    };
+    let mut patch = Patch::new(chunk_empty());
+    let mut matched = false;
+    // The old and new lines that the current hunk's header says are left
+    let mut hunk_lines_left: Option<(usize, usize)> = None;
+    let mut hunk_parents = 1;
 
     loop {
+        // With --quiet, the answer is known as soon as one patch matches
+        if matched && config.quiet {
+            return Ok(true);
+        }
         let mut line_buf: Vec<u8> = Vec::new();
-        if reader.read_until(b'\n', &mut line_buf).expect("Failed to read.") == 0 {
+        if reader.read_until(b'\n', &mut line_buf)? == 0 {
             break;
         }
         line_num += 1;
@@ -204,36 +1106,72 @@ fn process_lines<'a>(
             }
         };
         
-        let line_ansi_stripped = strip_ansi_codes(&line);
-        let mut line_stripped = line_ansi_stripped.into_owned();
-        if line_stripped.ends_with("\n") {
-            line_stripped.pop();
-            if line_stripped.ends_with("\r") {
-                line_stripped.pop();
+        let line_stripped = strip_line(&line);
+
+        // Whether the current hunk has all the lines its header says it has,
+        // so that a line starting with '-' can't be part of it
+        let hunk_over = hunk_lines_left == Some((0, 0));
+        if line_stripped.starts_with("@@") {
+            hunk_parents = hunk_parent_count(&line_stripped);
+            hunk_lines_left = parse_hunk_header(&line_stripped).ok()
+                .map(|header| (header.old.len, header.new.len));
+        } else if matches!(state, State::HunkHead | State::HunkBodyDiff | State::HunkBodyTail) {
+            // `git format-patch` ends each patch with a `-- ` line, which
+            // looks like a removed line unless the hunk is known to be over
+            if line_stripped == "-- " && hunk_over {
+                state = State::Signature;
+                continue;
+            }
+            // For a combined diff, the old side is the first parent
+            let kind = body_line_kind(&line_stripped, hunk_parents);
+            if let (Some((old_left, new_left)), Some(kind)) = (&mut hunk_lines_left, kind) {
+                if in_parent(&line_stripped, kind, 0) {
+                    *old_left = old_left.saturating_sub(1);
+                }
+                if kind != LineKind::Removed {
+                    *new_left = new_left.saturating_sub(1);
+                }
             }
         }
 
+        // The notes `diff -r` writes between files aren't part of any file's
+        // diff. An email's message isn't indented, so it could have a line
+        // that looks like one.
+        if !matches!(state, State::PatchHeader | State::Signature) && is_diff_r_note(&line_stripped) {
+            continue;
+        }
+
         match state {
             State::Start => {
-                if line_stripped.starts_with("commit ") {
-                    patch = Patch {
-                        patch_header: chunk_from(line),
-                        files: Vec::new(),
-                    };
+                if is_patch_start(&line_stripped) {
+                    patch = Patch::new(chunk_from(line));
                     state = State::PatchHeader;
+                } else if is_file_start(&line_stripped)
+                    || line_stripped.starts_with("--- ")
+                    || is_binary_files_line(&line_stripped)
+                {
+                    // `git diff` and `git diff --cached` output has no commit
+                    // header, so treat it as a single patch without one. Plain
+                    // `diff -u` output can start straight from the `---` line.
+                    patch.start_file(line);
+                    state = State::FileHeader;
                 } else {
-                    bail!("Invalid patch. Expected commit message");
+                    bail!("Invalid patch. Expected commit message or diff");
                 }
             }
             State::PatchHeader => {
-                if line_stripped.starts_with("diff --git") {
-                    patch.files.push(FileDiff {
-                        file_header: chunk_from(line),
-                        hunks: Vec::new(),
-                    });
+                if is_git_file_start(&line_stripped) {
+                    patch.start_file(line);
                     state = State::FileHeader;
+                } else if is_patch_start(&line_stripped) {
+                    // Merges have no diff unless git is given -m, -c or --cc
+                    matched |= start_patch(config, matcher, filters, &mut patch, line, print_state, writer)?;
                 } else {
-                    patch.patch_header.lines.push(line);
+                    let header = match patch.parent_headers.last_mut() {
+                        Some(header) => header,
+                        None => &mut patch.patch_header,
+                    };
+                    header.lines.push(line);
                 }
             }
             State::FileHeader => {
@@ -241,10 +1179,27 @@ fn process_lines<'a>(
                 if line_stripped.starts_with("@@") {
                     file.hunks.push(Hunk {
                         header: line,
+                        parents: hunk_parent_count(&line_stripped),
                         context_head: chunk_empty(),
                         diffs: Vec::new(),
                     });
                     state = State::HunkHead;
+                } else if is_file_start(&line_stripped) || (
+                    // A second `---` line is the next file of a `diff -u`
+                    // without `diff` lines
+                    line_stripped.starts_with("--- ")
+                        && file.file_header.lines.iter().any(|line| strip_line(line).starts_with("--- "))
+                ) || (
+                    // and a second `Binary files` line is the next binary
+                    // file of a `diff -r`
+                    is_binary_files_line(&line_stripped)
+                        && file.file_header.lines.iter().any(|line| is_binary_files_line(&strip_line(line)))
+                ) {
+                    // Mode changes, renames and binary files have no hunks
+                    patch.start_file(line);
+                } else if is_patch_start(&line_stripped) {
+                    matched |= start_patch(config, matcher, filters, &mut patch, line, print_state, writer)?;
+                    state = State::PatchHeader;
                 } else {
                     file.file_header.lines.push(line);
                 }
@@ -252,41 +1207,39 @@ fn process_lines<'a>(
             State::HunkHead => {
                 let file = patch.files.last_mut().expect("Expected a file diff");
                 let hunk = file.hunks.last_mut().expect("Expected a hunk");
-                if line_stripped.starts_with(" ") {
-                    hunk.context_head.lines.push(line);
-                } else if line_stripped.starts_with("+") || line_stripped.starts_with("-") {
-                    hunk.diffs.push(HunkDiffWithTail {
-                        diff: chunk_from(line),
-                        context_tail: chunk_empty(),
-                    });
-                    state = State::HunkBodyDiff;
-                } else {
-                    bail!("Unknown state in hunk head");
+                match body_line_kind(&line_stripped, hunk_parents) {
+                    Some(LineKind::Context) if !line_stripped.is_empty() => {
+                        hunk.context_head.lines.push(line);
+                    }
+                    Some(LineKind::Added | LineKind::Removed) => {
+                        hunk.diffs.push(HunkDiffWithTail {
+                            diff: chunk_from(line),
+                            context_tail: chunk_empty(),
+                        });
+                        state = State::HunkBodyDiff;
+                    }
+                    _ => bail!("Unknown state in hunk head"),
                 }
             }
             State::HunkBodyDiff => {
                 let file = patch.files.last_mut().expect("Expected a file diff");
                 let hunk = file.hunks.last_mut().expect("Expected a hunk");
                 let hunk_diff = hunk.diffs.last_mut().expect("Expected a hunk diff");
-                if line_stripped.starts_with("+") || line_stripped.starts_with("-") {
+                if is_file_start(&line_stripped)
+                    || (hunk_over && line_stripped.starts_with("--- "))
+                    || is_binary_files_line(&line_stripped)
+                {
+                    patch.start_file(line);
+                    state = State::FileHeader;
+                } else if is_diff_line(&line_stripped, hunk_parents) {
                     hunk_diff.diff.lines.push(line);
-                } else if line_stripped.starts_with(" ")
-                    || line_stripped.is_empty()
+                } else if body_line_kind(&line_stripped, hunk_parents) == Some(LineKind::Context)
                     || line_stripped == "\\ No newline at end of file"
                 {
                     hunk_diff.context_tail.lines.push(line);
                     state = State::HunkBodyTail;
-                } else if line_stripped.starts_with("diff --git") {
-                    patch.files.push(FileDiff {
-                        file_header: chunk_from(line),
-                        hunks: Vec::new(),
-                    });
-                    state = State::FileHeader;
-                } else if line_stripped.starts_with("commit ") {
-                    patch = Patch {
-                        patch_header: chunk_from(line),
-                        files: Vec::new(),
-                    };
+                } else if is_patch_start(&line_stripped) {
+                    matched |= start_patch(config, matcher, filters, &mut patch, line, print_state, writer)?;
                     state = State::PatchHeader;
                 } else {
                     bail!("Unknown state in hunk body");
@@ -296,40 +1249,48 @@ fn process_lines<'a>(
                 let file = patch.files.last_mut().expect("Expected a file diff");
                 let hunk = file.hunks.last_mut().expect("Expected a hunk");
                 let hunk_diff = hunk.diffs.last_mut().expect("Expected a hunk diff");
-                if line_stripped.starts_with(" ")
-                    || line_stripped.is_empty()
+                if is_file_start(&line_stripped)
+                    || (hunk_over && line_stripped.starts_with("--- "))
+                    || is_binary_files_line(&line_stripped)
+                {
+                    patch.start_file(line);
+                    state = State::FileHeader;
+                } else if body_line_kind(&line_stripped, hunk_parents) == Some(LineKind::Context)
                     || line_stripped == "\\ No newline at end of file"
                 {
                     hunk_diff.context_tail.lines.push(line);
-                } else if line_stripped.starts_with("+") || line_stripped.starts_with("-") {
-                    hunk_diff.diff.lines.push(line);
+                } else if is_diff_line(&line_stripped, hunk_parents) {
+                    hunk.diffs.push(HunkDiffWithTail {
+                        diff: chunk_from(line),
+                        context_tail: chunk_empty(),
+                    });
                     state = State::HunkBodyDiff;
                 } else if line_stripped.starts_with("@@") {
                     file.hunks.push(Hunk {
                         header: line,
+                        parents: hunk_parent_count(&line_stripped),
                         context_head: chunk_empty(),
                         diffs: Vec::new(),
                     });
                     state = State::HunkHead;
-                } else if line_stripped.starts_with("diff --git") {
-                    patch.files.push(FileDiff {
-                        file_header: chunk_from(line),
-                        hunks: Vec::new(),
-                    });
-                    state = State::FileHeader;
-                } else if line_stripped.starts_with("commit ") {
-                    process_patch(config, &patch, &mut writer)?;
-                    patch = Patch {
-                        patch_header: chunk_from(line),
-                        files: Vec::new(),
-                    };
+                } else if is_patch_start(&line_stripped) {
+                    matched |= start_patch(config, matcher, filters, &mut patch, line, print_state, writer)?;
                     state = State::PatchHeader;
                 } else {
                     bail!("Unknown state in hunk tail");
                 }
             }
+            State::Signature => {
+                if is_patch_start(&line_stripped) {
+                    matched |= start_patch(config, matcher, filters, &mut patch, line, print_state, writer)?;
+                    state = State::PatchHeader;
+                }
+            }
         };
     }
-    process_patch(config, &patch, &mut writer)?;
-    Ok(())
+    // An input with no patches in it has nothing to match, not even with -v
+    if !matches!(state, State::Start) {
+        matched |= process_patch(config, matcher, filters, &mut patch, print_state, writer)?;
+    }
+    Ok(matched)
 }
@@ -0,0 +1,171 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+use simple_error::{SimpleError, simple_error};
+
+use crate::{body_line_content, parse_args::{CaseSensitivity, Config, PatternExpr, PatternKind}};
+
+/// A `PatternExpr` with each pattern replaced by its index in `Matcher::regexes`
+enum CompiledExpr {
+    Pattern(usize),
+    Not(Box<CompiledExpr>),
+    And(Box<CompiledExpr>, Box<CompiledExpr>),
+    Or(Box<CompiledExpr>, Box<CompiledExpr>),
+}
+
+impl CompiledExpr {
+    fn eval(&self, pattern_matches: &dyn Fn(usize) -> bool) -> bool {
+        match self {
+            CompiledExpr::Pattern(index) => pattern_matches(*index),
+            CompiledExpr::Not(expr) => !expr.eval(pattern_matches),
+            CompiledExpr::And(left, right) => left.eval(pattern_matches) && right.eval(pattern_matches),
+            CompiledExpr::Or(left, right) => left.eval(pattern_matches) || right.eval(pattern_matches),
+        }
+    }
+}
+
+/// The compiled form of the patterns in a `Config`
+pub struct Matcher {
+    regexes: Vec<Regex>,
+    /// Whether each pattern is under an odd number of `--not`s, in which case
+    /// lines matching it are not what the user is looking for
+    negated: Vec<bool>,
+    expr: CompiledExpr,
+    pattern_kind: PatternKind,
+}
+
+/// Whether a pattern contains an uppercase letter that it matches literally.
+/// Escape sequences such as `\S` and `\p{Lu}` in regular expressions don't
+/// count, the same as ripgrep's smart case.
+fn has_uppercase_literal(pattern: &str, pattern_kind: &PatternKind) -> bool {
+    if *pattern_kind == PatternKind::Fixed {
+        return pattern.chars().any(char::is_uppercase);
+    }
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            // Skip the escaped character, and the name of a \p{Name} class
+            if let Some('p' | 'P') = chars.next() {
+                if chars.next() == Some('{') {
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            break;
+                        }
+                    }
+                }
+            }
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+fn compile_pattern(config: &Config, search_string: &str) -> Result<Regex, SimpleError> {
+    let pattern = match config.pattern_kind {
+        PatternKind::Fixed => regex::escape(search_string),
+        PatternKind::Regex => search_string.to_string(),
+    };
+    let case_insensitive = match config.case_sensitivity {
+        CaseSensitivity::Sensitive => false,
+        CaseSensitivity::Insensitive => true,
+        CaseSensitivity::Smart => !has_uppercase_literal(search_string, &config.pattern_kind),
+    };
+    // Unicode mode is on by default, so case folding applies to all of
+    // Unicode rather than only ASCII.
+    RegexBuilder::new(&pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|err| {
+            simple_error!("Invalid regular expression '{}': {}", search_string, err)
+        })
+}
+
+fn compile_expr(
+    config: &Config,
+    expr: &PatternExpr,
+    negated: bool,
+    regexes: &mut Vec<Regex>,
+    negated_patterns: &mut Vec<bool>
+) -> Result<CompiledExpr, SimpleError> {
+    let mut compile = |expr: &PatternExpr, negated: bool| -> Result<Box<CompiledExpr>, SimpleError> {
+        Ok(Box::new(compile_expr(config, expr, negated, regexes, negated_patterns)?))
+    };
+    Ok(match expr {
+        PatternExpr::Pattern(search_string) => {
+            regexes.push(compile_pattern(config, search_string)?);
+            negated_patterns.push(negated);
+            CompiledExpr::Pattern(regexes.len() - 1)
+        }
+        PatternExpr::Not(expr) => CompiledExpr::Not(compile(expr, !negated)?),
+        PatternExpr::And(left, right) => CompiledExpr::And(compile(left, negated)?, compile(right, negated)?),
+        PatternExpr::Or(left, right) => CompiledExpr::Or(compile(left, negated)?, compile(right, negated)?),
+    })
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Matcher, SimpleError> {
+        // Without any -e patterns, the positional PATTERN is the only one
+        let search_string_expr = PatternExpr::Pattern(config.search_string.clone());
+        let expr = config.pattern_expr.as_ref().unwrap_or(&search_string_expr);
+        let mut regexes = Vec::new();
+        let mut negated = Vec::new();
+        let expr = compile_expr(config, expr, false, &mut regexes, &mut negated)?;
+        Ok(Matcher { regexes, negated, expr, pattern_kind: config.pattern_kind })
+    }
+
+    /// The part of a hunk body line with `columns` leading '+', '-' or ' '
+    /// columns that is searched. A fixed string is searched for in the whole
+    /// line, so `+    black` finds an added line, while a regular expression
+    /// is matched against what comes after the columns so that `^` is the
+    /// start of the line in the file.
+    pub fn searched_part<'a>(&self, line: &'a str, columns: usize) -> &'a str {
+        match self.pattern_kind {
+            PatternKind::Fixed => line,
+            PatternKind::Regex => body_line_content(line, columns),
+        }
+    }
+
+    /// Check a line that has already had its ANSI codes and line ending
+    /// removed against the whole expression
+    pub fn is_match(&self, line: &str) -> bool {
+        self.expr.eval(&|index| self.regexes[index].is_match(line))
+    }
+
+    /// Evaluate the expression given which patterns matched somewhere
+    pub fn eval(&self, pattern_matches: &[bool]) -> bool {
+        self.expr.eval(&|index| pattern_matches[index])
+    }
+
+    pub fn pattern_count(&self) -> usize {
+        self.regexes.len()
+    }
+
+    pub fn pattern_is_match(&self, index: usize, line: &str) -> bool {
+        self.regexes[index].is_match(line)
+    }
+
+    /// Whether a line contains any of the patterns that aren't negated, and so
+    /// is one of the lines the user is looking for
+    pub fn is_positive_match(&self, line: &str) -> bool {
+        self.regexes.iter().zip(&self.negated)
+            .any(|(regex, negated)| !negated && regex.is_match(line))
+    }
+
+    /// Where the patterns that aren't negated are found in a line
+    pub fn positive_match_ranges(&self, line: &str) -> Vec<Range<usize>> {
+        self.regexes.iter().zip(&self.negated)
+            .filter(|(_, negated)| !**negated)
+            .flat_map(|(regex, _)| regex.find_iter(line).map(|found| found.range()))
+            .collect()
+    }
+
+    pub fn is_negated(&self, index: usize) -> bool {
+        self.negated[index]
+    }
+
+    /// The number of non-overlapping matches of one pattern in a line
+    pub fn count(&self, index: usize, line: &str) -> usize {
+        self.regexes[index].find_iter(line).count()
+    }
+}
@@ -0,0 +1,166 @@
+use simple_error::SimpleError;
+
+use crate::{
+    body_line_content, hunk_header::parse_hunk_header, matcher::Matcher,
+    parse_args::{Config, MatchScope}, strip_line, Hunk, PatchMatches,
+};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum LineKind {
+    Added,
+    Removed,
+    Context,
+}
+
+/// Whether a hunk body line was added, removed or is context, from the '+',
+/// '-' or ' ' columns at its start. A combined diff of a merge has a column
+/// per parent, and a line with a '-' in any of them isn't in the merge.
+/// Returns `None` for a line that isn't a hunk body line.
+pub fn body_line_kind(line: &str, parents: usize) -> Option<LineKind> {
+    let mut kind = LineKind::Context;
+    // Some editors remove the trailing spaces of empty context lines
+    for column in line.chars().take(parents) {
+        match column {
+            '+' => kind = LineKind::Added,
+            '-' => return Some(LineKind::Removed),
+            ' ' => (),
+            _ => return None,
+        }
+    }
+    Some(kind)
+}
+
+/// Whether a body line is in the parent with the given column, which is the
+/// case for a line removed from it and for a context or added line that it
+/// already had
+pub fn in_parent(line: &str, kind: LineKind, column: usize) -> bool {
+    let column = line.chars().nth(column).unwrap_or(' ');
+    match kind {
+        LineKind::Removed => column == '-',
+        LineKind::Added | LineKind::Context => column == ' ',
+    }
+}
+
+/// How a line of a hunk body differs from one of the parents it is a diff
+/// against
+pub struct ParentLine {
+    /// The '+', '-' or ' ' column for the parent. For a line that was removed
+    /// from a different parent of a merge, ' ' means it isn't in this one.
+    pub kind: LineKind,
+    /// `None` if the line isn't in the parent
+    pub line: Option<usize>,
+}
+
+/// A line of a hunk body with its position in the old and new file
+pub struct NumberedLine {
+    pub kind: LineKind,
+    /// `None` for an added line. For a combined diff, the line in the first
+    /// parent.
+    pub old_line: Option<usize>,
+    /// `None` for a removed line
+    pub new_line: Option<usize>,
+    /// One entry per parent, so more than one for a combined diff of a merge
+    pub parents: Vec<ParentLine>,
+    /// The line without ANSI codes or its line ending
+    pub text: String,
+    /// The index of the diff block the line is in, or `None` for a context
+    /// line
+    pub diff: Option<usize>,
+}
+
+impl NumberedLine {
+    /// The line number in the new file, or for a removed line in the first
+    /// parent it was removed from
+    pub fn line_number(&self) -> usize {
+        self.new_line
+            .or_else(|| self.parents.iter().find_map(|parent| parent.line))
+            .unwrap_or(0)
+    }
+
+    /// The line without its leading '+', '-' or ' ' columns
+    pub fn content(&self) -> &str {
+        body_line_content(&self.text, self.parents.len())
+    }
+}
+
+/// Number the lines of a hunk body from the ranges in its header
+pub fn numbered_lines(hunk: &Hunk) -> Result<Vec<NumberedLine>, SimpleError> {
+    let header = parse_hunk_header(&strip_line(&hunk.header))?;
+    let mut parent_lines: Vec<usize> = std::iter::once(header.old.start)
+        .chain(header.other_parents.iter().map(|range| range.start))
+        .collect();
+    let mut new_line = header.new.start;
+    let body = hunk.context_head.lines.iter().map(|line| (line, None))
+        .chain(hunk.diffs.iter().enumerate().flat_map(|(diff_index, diff)| {
+            diff.diff.lines.iter().map(move |line| (line, Some(diff_index)))
+                .chain(diff.context_tail.lines.iter().map(|line| (line, None)))
+        }));
+    let mut lines = Vec::new();
+    for (line, diff) in body {
+        let line = strip_line(line);
+        // "\ No newline at end of file" is not a line of the file
+        let Some(kind) = body_line_kind(&line, hunk.parents) else {
+            continue;
+        };
+        let parents: Vec<ParentLine> = parent_lines.iter_mut().enumerate()
+            .map(|(column, parent_line)| {
+                let in_parent = in_parent(&line, kind, column);
+                let parent = ParentLine {
+                    kind: match line.chars().nth(column) {
+                        Some('+') => LineKind::Added,
+                        Some('-') => LineKind::Removed,
+                        _ => LineKind::Context,
+                    },
+                    line: in_parent.then_some(*parent_line),
+                };
+                *parent_line += in_parent as usize;
+                parent
+            })
+            .collect();
+        let new = (kind != LineKind::Removed).then_some(new_line);
+        new_line += new.is_some() as usize;
+        lines.push(NumberedLine {
+            kind,
+            old_line: parents[0].line,
+            new_line: new,
+            parents,
+            text: line,
+            diff,
+        });
+    }
+    Ok(lines)
+}
+
+/// Whether a line of a hunk is one of the ones that matched, or with `-v` one
+/// of the ones that didn't. Only lines in the fields that were searched count,
+/// and only in the diff blocks or context that `matches` selects, since `-S`
+/// and `--scope` can rule out a block even though its lines contain a pattern.
+pub fn line_selected(
+    config: &Config,
+    matcher: &Matcher,
+    matches: &PatchMatches,
+    file_index: usize,
+    hunk_index: usize,
+    line: &NumberedLine
+) -> bool {
+    let block_selected = match line.diff {
+        Some(diff_index) => matches.diff_selected(file_index, hunk_index, diff_index),
+        None => matches.context_selected(file_index, hunk_index),
+    };
+    if !block_selected {
+        return false;
+    }
+    let searched = match line.kind {
+        LineKind::Added => config.match_on.added,
+        LineKind::Removed => config.match_on.removed,
+        LineKind::Context => config.match_on.context,
+    };
+    // At line scope the whole expression decides which lines matched, and
+    // otherwise it's the lines with a pattern that isn't negated
+    let text = matcher.searched_part(&line.text, line.parents.len());
+    let is_match = match config.scope {
+        MatchScope::Line => matcher.is_match(text),
+        _ => matcher.is_positive_match(text),
+    };
+    searched && is_match != config.invert_match
+}
@@ -1,6 +1,8 @@
-use std::{collections::HashMap, env::Args};
+use std::{collections::HashMap, env::Args, iter::Peekable, slice::Iter};
 use simple_error::{SimpleError, bail, simple_error};
 
+use crate::date::{parse_date, Date};
+
 #[derive(Default, PartialEq, Debug)]
 pub enum UTF8Strategy {
     #[default]
@@ -11,7 +13,8 @@ pub enum UTF8Strategy {
 
 #[derive(Default)]
 pub struct PatchSections {
-    pub diff: bool,
+    pub added: bool,
+    pub removed: bool,
     pub context: bool,
     pub file_header: bool,
     pub patch_header: bool,
@@ -20,6 +23,92 @@ pub struct PatchSections {
 pub enum OutputConfig {
     Sections(PatchSections),
     CommitHash,
+    /// Only the matching changes, as a patch that can be applied with `git apply`
+    Patch,
+    /// The number of matching lines in each patch, or in each file with
+    /// `--granularity file` or `hunk`
+    Count,
+    /// `commit<TAB>path` for each file with a match
+    FilesWithMatches,
+    /// `commit<TAB>path` for each file without a match
+    FilesWithoutMatch,
+    /// `commit:path:line:content` for each matching line
+    Lines,
+    /// `path:line:column: commit content` for each matching line, which is
+    /// what Vim's quickfix list and Emacs' grep-mode read
+    Quickfix,
+    /// An array with an object for each matching patch
+    Json,
+    /// An object for each matching patch, one per line
+    JsonLines,
+}
+
+/// How the search pattern is interpreted
+#[derive(Default, PartialEq, Debug, Clone, Copy)]
+pub enum PatternKind {
+    #[default]
+    Fixed,
+    Regex,
+}
+
+#[derive(Default, PartialEq, Debug)]
+pub enum CaseSensitivity {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Case insensitive unless the pattern contains an uppercase character
+    Smart,
+}
+
+/// When added and removed lines count as a match, named after the
+/// `git log -G` and `git log -S` options
+#[derive(Default, PartialEq, Debug)]
+pub enum Pickaxe {
+    /// Any added or removed line that matches
+    #[default]
+    LineMatch,
+    /// Only files where the number of occurrences differs between the removed
+    /// and added lines, so moved and re-indented lines don't match
+    OccurrenceCount,
+}
+
+/// A boolean combination of patterns built from `-e`, `--and`, `--or` and
+/// `--not`
+#[derive(PartialEq, Debug, Clone)]
+pub enum PatternExpr {
+    Pattern(String),
+    Not(Box<PatternExpr>),
+    And(Box<PatternExpr>, Box<PatternExpr>),
+    Or(Box<PatternExpr>, Box<PatternExpr>),
+}
+
+/// Where all the patterns in a `PatternExpr` have to be found for it to match
+#[derive(Default, PartialEq, Debug)]
+pub enum MatchScope {
+    #[default]
+    Line,
+    Hunk,
+    File,
+    Patch,
+}
+
+/// How much of a patch to print when part of it matches
+#[derive(Default, PartialEq, Debug)]
+pub enum Granularity {
+    #[default]
+    Patch,
+    File,
+    Hunk,
+}
+
+/// When to use color in the output
+#[derive(Default, PartialEq, Debug)]
+pub enum ColorChoice {
+    /// Only when writing to a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
 }
 
 impl Default for OutputConfig {
@@ -33,19 +122,67 @@ pub struct Config {
     pub match_on: PatchSections,
     pub output: OutputConfig,
     pub search_string: String,
-    pub decode_strategy: UTF8Strategy
+    /// Files and directories to read patches from instead of stdin
+    pub inputs: Vec<String>,
+    /// Set by `-e`, in which case `search_string` is not used
+    pub pattern_expr: Option<PatternExpr>,
+    pub scope: MatchScope,
+    /// Globs from `--path`. If there are any, only files matching one of them
+    /// are searched and printed.
+    pub paths: Vec<String>,
+    /// Globs from `--exclude-path`
+    pub exclude_paths: Vec<String>,
+    /// Globs from `--ref`. If there are any, or any `tags`, only commits that
+    /// one of them matches a ref of are searched.
+    pub refs: Vec<String>,
+    /// Globs from `--tag`, which only match tags
+    pub tags: Vec<String>,
+    /// Regexes from `--author`. If there are any, only commits whose author
+    /// matches one of them are searched.
+    pub authors: Vec<String>,
+    /// Regexes from `--committer`
+    pub committers: Vec<String>,
+    /// From `--since`, in seconds since the Unix epoch
+    pub since: Option<i64>,
+    /// From `--until`, in seconds since the Unix epoch
+    pub until: Option<i64>,
+    pub pattern_kind: PatternKind,
+    pub case_sensitivity: CaseSensitivity,
+    pub pickaxe: Pickaxe,
+    pub invert_match: bool,
+    /// Only search the diff of each merge against its first parent when
+    /// `git log -m` shows one against each parent
+    pub first_parent: bool,
+    /// Print nothing and stop at the first match
+    pub quiet: bool,
+    /// Add a column with the refs of each commit to the output that has one
+    /// line per commit or file
+    pub show_refs: bool,
+    /// Print each path only once with `FilesWithMatches` and `FilesWithoutMatch`
+    pub unique: bool,
+    /// Whether to highlight the matches in the printed lines, if `color` allows
+    pub highlight: bool,
+    pub color: ColorChoice,
+    pub decode_strategy: UTF8Strategy,
+    pub granularity: Granularity,
 }
 
 fn parse_patch_sections(input: &str) -> Result<PatchSections, SimpleError> {
     let mut sections = PatchSections {
-        diff: false,
+        added: false,
+        removed: false,
         context: false,
         file_header: false,
         patch_header: false,
     };
     for section_str in input.split(',') {
         match section_str {
-            "diff" => sections.diff = true,
+            "diff" => {
+                sections.added = true;
+                sections.removed = true;
+            }
+            "added" => sections.added = true,
+            "removed" => sections.removed = true,
             "context" => sections.context = true,
             "file_header" => sections.file_header = true,
             "patch_header" => sections.patch_header = true,
@@ -55,6 +192,65 @@ fn parse_patch_sections(input: &str) -> Result<PatchSections, SimpleError> {
     Ok(sections)
 }
 
+fn parse_date_arg(input: &str) -> Result<Date, SimpleError> {
+    parse_date(input).ok_or_else(|| simple_error!("Invalid date '{}'. Run `hunk -h` for help", input))
+}
+
+fn parse_color_choice(input: &str) -> Result<ColorChoice, SimpleError> {
+    match input {
+        "auto" => Ok(ColorChoice::Auto),
+        "always" => Ok(ColorChoice::Always),
+        "never" => Ok(ColorChoice::Never),
+        other => bail!("Unknown value '{}'. Run `hunk -h` for help", other),
+    }
+}
+
+enum PatternToken {
+    Pattern(String),
+    And,
+    Or,
+    Not,
+}
+
+/// Build a `PatternExpr` from `-e`, `--and`, `--or` and `--not` in the order
+/// they were given. As in `git grep`, `--not` binds the tightest, then
+/// `--and`, and patterns with no operator between them are joined by `--or`.
+fn build_pattern_expr(tokens: &[PatternToken]) -> Result<PatternExpr, SimpleError> {
+    type Tokens<'a> = Peekable<Iter<'a, PatternToken>>;
+
+    fn parse_or(tokens: &mut Tokens) -> Result<PatternExpr, SimpleError> {
+        let mut expr = parse_and(tokens)?;
+        while let Some(token) = tokens.peek() {
+            if let PatternToken::Or = token {
+                tokens.next();
+            }
+            expr = PatternExpr::Or(Box::new(expr), Box::new(parse_and(tokens)?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(tokens: &mut Tokens) -> Result<PatternExpr, SimpleError> {
+        let mut expr = parse_not(tokens)?;
+        while let Some(PatternToken::And) = tokens.peek() {
+            tokens.next();
+            expr = PatternExpr::And(Box::new(expr), Box::new(parse_not(tokens)?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(tokens: &mut Tokens) -> Result<PatternExpr, SimpleError> {
+        match tokens.next() {
+            Some(PatternToken::Not) => Ok(PatternExpr::Not(Box::new(parse_not(tokens)?))),
+            Some(PatternToken::Pattern(pattern)) => Ok(PatternExpr::Pattern(pattern.clone())),
+            Some(PatternToken::And) => bail!("Expected a pattern before 'and'. Run `hunk -h` for help"),
+            Some(PatternToken::Or) => bail!("Expected a pattern before 'or'. Run `hunk -h` for help"),
+            None => bail!("Expected a pattern at the end of the expression. Run `hunk -h` for help"),
+        }
+    }
+
+    parse_or(&mut tokens.iter().peekable())
+}
+
 fn print_help() {
     struct HelpData {
         two_arg_params: HashMap<&'static str, &'static str>,
@@ -64,24 +260,61 @@ fn print_help() {
 
     let help_data = HelpData {
         two_arg_params: HashMap::from([
-            ("--match-fields", "Which fields of the patch to search for the string. Takes a comma-separated list of values. Valid values are 'diff', 'context', 'file_header', and 'patch_header'"),
-            ("--print-fields", "Which fields of the patch to print to stdout when a match is found. Takes a comma-separated list of values. Valid values are 'diff', 'context', 'file_header', and 'patch_header'"),
-            ("--invalid-utf8", "How to handle invalid UTF-8 lines. Specify one of 'lossy', 'panic', or 'skip-line'")
+            ("--match-fields", "Which fields of the patch to search for the string. Takes a comma-separated list of values. Valid values are 'added', 'removed', 'diff' (both added and removed), 'context', 'file_header', and 'patch_header'"),
+            ("--print-fields", "Which fields of the patch to print to stdout when a match is found. Takes a comma-separated list of values. Valid values are 'added', 'removed', 'diff' (both added and removed), 'context', 'file_header', and 'patch_header'"),
+            ("--invalid-utf8", "How to handle invalid UTF-8 lines. Specify one of 'lossy', 'panic', or 'skip-line'"),
+            ("-e", "A pattern to search for. Can be given multiple times and combined with --and, --or and --not, in which case PATTERN is not given"),
+            ("--input", "A file to read patches from, '-' for stdin, or a directory of *.patch files such as the output of `git format-patch`. Can be given multiple times, in which case the output shows which input each match came from"),
+            ("--scope", "Where all the patterns combined with --and, --or and --not have to be found. Specify one of 'line' (the default), 'hunk', 'file' or 'patch'"),
+            ("--path", "Only search and print files whose path matches this glob. '*' does not match '/' while '**' matches any number of directories, and a glob that matches a directory matches everything in it. Can be given multiple times"),
+            ("--exclude-path", "Don't search or print files whose path matches this glob. Can be given multiple times"),
+            ("--ref", "Only search commits with a branch, tag or HEAD that matches this glob, from `git log --decorate`. Can be given multiple times"),
+            ("--tag", "Like --ref, but only tags match. Can be given multiple times"),
+            ("--author", "Only search commits whose author, as 'Name <email>', matches this regular expression. Can be given multiple times"),
+            ("--committer", "Like --author, but for the committer, which `git log` only shows with --format=fuller. Can be given multiple times"),
+            ("--since", "Only search commits from this date on, such as '2024-01-31', '2024-01-31 12:00', '2024-01-31T12:00:00+01:00', or '@1706702400' for seconds since the epoch. Dates are in UTC unless they have a timezone. Commits are compared by their commit date with `git log --format=fuller`, and by their author date otherwise"),
+            ("--until", "Only search commits up to this date, in the same form as --since. A date without a time includes the whole day"),
+            ("--color", "When to highlight matches with --highlight. Specify one of 'auto' (the default) to only highlight when writing to a terminal, 'always' or 'never'. Can also be given as --color=WHEN"),
+            ("--granularity", "How much of a patch to print when a match is found. Specify one of 'patch' (the default), 'file' to print only the files that matched, or 'hunk' to print only the hunks that matched")
         ]),
         one_arg_params: HashMap::from([
             ("--print-commits", "Print only the hashes of commits that contain the string"),
+            ("--regex", "Treat PATTERN as a regular expression rather than a fixed string. Lines in hunks are matched without their leading '+', '-' or ' ', so ^ is the start of the line in the file"),
+            ("--ignore-case, -i", "Match PATTERN case insensitively"),
+            ("--smart-case", "Match PATTERN case insensitively if it is all lowercase, and case sensitively otherwise"),
+            ("-G", "Match any added or removed line that contains PATTERN, like `git log -G`. This is the default"),
+            ("-S", "Only match files where the number of occurrences of PATTERN differs between the removed and added lines, like `git log -S`"),
+            ("--and", "Only match if the patterns on both sides of it match. Binds more tightly than --or"),
+            ("--or", "Match if either of the patterns on both sides of it match. This is the default between two patterns"),
+            ("--not", "Match if the pattern after it does not match"),
+            ("--invert-match, -v", "Select the patches, or with --granularity the files or hunks, that do not contain the string"),
+            ("--first-parent", "With `git log -m`, which shows the diff of a merge against each of its parents, only search the diff against the first parent. By default a merge matches if any of its diffs match"),
+            ("--highlight", "Highlight the matches in the printed lines. Colors from `git log -p --color` are kept around the highlighted parts"),
+            ("--print-lines", "Print each matching line as 'commit:path:line:content', like `git grep`. Removed lines have their line number in the old file, and the commit is left out for patches without a commit header. With -v, print the lines that don't match instead"),
+            ("--count, -c", "Print the number of matching added, removed and context lines in each matching patch as 'commit<TAB>added<TAB>removed<TAB>context'. With --granularity file or hunk, print them for each matching file as 'commit<TAB>path<TAB>added<TAB>removed<TAB>context'"),
+            ("--files-with-matches, -l", "Print 'commit<TAB>path' for each file that contains the string"),
+            ("--files-without-match", "Print 'commit<TAB>path' for each file that does not contain the string"),
+            ("--show-refs", "With --print-commits, --count, --files-with-matches and --files-without-match, add a column after the commit with the branches and tags that point at it, from `git log --decorate`"),
+            ("--unique", "With --files-with-matches or --files-without-match, print each path only once and without the commit"),
+            ("--quickfix", "Print each matching line as 'path:line:column: [input] commit content', which Vim's quickfix list and Emacs' grep-mode can jump to. The column is that of the first match in the line"),
+            ("--print-json", "Print a JSON array with an object for each matching patch, containing its commit hash, author, date, message and the files and hunks that would be printed, each hunk with the lines that matched and their line numbers"),
+            ("--print-jsonl", "Like --print-json, but print each object on its own line instead of in an array"),
+            ("--print-patch", "Print only the changes that contain the string, as a patch that can be applied with `git apply`"),
+            ("--quiet, -q", "Print nothing and stop reading at the first match. The exit status is 0 if there was a match and 1 otherwise"),
             ("--help, -h", "Show this message and exit")
         ]),
         positional_params: HashMap::from([
-            ("PATTERN", "The string to search for")
+            ("PATTERN", "The string to search for"),
+            ("INPUT", "The same as --input. Patches are read from stdin if there are no inputs")
         ]),
     };
-    println!("Usage: hunk [OPTION...] [--] PATTERN");
-    println!("");
+    println!("Usage: hunk [OPTION...] [--] PATTERN [INPUT...]");
+    println!("       hunk [OPTION...] -e PATTERN [--and|--or|--not] ... [INPUT...]");
+    println!();
     for (k, v) in help_data.positional_params {
         println!("{:15}: {}", k, v)
     }
-    println!("");
+    println!();
     println!("OPTIONS:");
     for (k, v) in help_data.one_arg_params {
         println!("{:15}: {}", k, v)
@@ -89,6 +322,8 @@ fn print_help() {
     for (k, v) in help_data.two_arg_params {
         println!("{:15}: {}", k, v)
     }
+    println!();
+    println!("The exit status is 0 if anything matched, 1 if nothing did, and 2 if there was an error.");
 }
 
 pub fn parse_args(args: &[&str]) -> Result<Config, SimpleError> {
@@ -96,13 +331,29 @@ pub fn parse_args(args: &[&str]) -> Result<Config, SimpleError> {
         has_search_string: bool,
         no_more_options: bool,
         has_print_option: bool,
+        has_pattern_option: bool,
+        pattern_tokens: Vec<PatternToken>,
+    }
+
+    /// Only one of the options that choose what to print can be given
+    fn set_output(state: &mut ParsingState, config: &mut Config, output: OutputConfig) -> Result<(), SimpleError> {
+        if state.has_print_option {
+            return Err(simple_error!("Only one option that chooses the output can be given. Run `hunk -h` for help"));
+        }
+        config.output = output;
+        state.has_print_option = true;
+        Ok(())
     }
 
     fn parse_slice(args: &[&str], state: &mut ParsingState, config: &mut Config) -> Result<(), SimpleError>{
         match &args {
             [arg, rest @ ..] if state.no_more_options => {
-                config.search_string = arg.to_string();
-                state.has_search_string = true;
+                if state.has_search_string {
+                    config.inputs.push(arg.to_string());
+                } else {
+                    config.search_string = arg.to_string();
+                    state.has_search_string = true;
+                }
                 parse_slice(rest, state, config)
             }
             ["--match-fields", match_fields, rest @ ..] => {
@@ -111,23 +362,178 @@ pub fn parse_args(args: &[&str]) -> Result<Config, SimpleError> {
             }
             ["--match-fields"] => Err(simple_error!("Expected argument for 'match-fields'. Run `hunk -h` for help")),
             ["--print-fields", print_fields, rest @ ..] => {
-                if state.has_print_option { 
-                    Err(simple_error!("Cannot have both print-commits and print-fields. Run `hunk -h` for help"))
-                } else {
-                    config.output = OutputConfig::Sections(parse_patch_sections(print_fields)?);
-                    state.has_print_option = true;
-                    parse_slice(rest, state, config)
-                }
+                set_output(state, config, OutputConfig::Sections(parse_patch_sections(print_fields)?))?;
+                parse_slice(rest, state, config)
             }
             ["--print-fields"] => Err(simple_error!("Expected argument for 'print-fields'. Run `hunk -h` for help")),
             ["--print-commits", rest @ ..] => {
-                if state.has_print_option { 
-                    Err(simple_error!("Cannot have both print-commits and print-fields. Run `hunk -h` for help"))
-                } else {
-                    config.output = OutputConfig::CommitHash;
-                    state.has_print_option = true;
-                    parse_slice(rest, state, config)
+                set_output(state, config, OutputConfig::CommitHash)?;
+                parse_slice(rest, state, config)
+            }
+            ["--print-patch", rest @ ..] => {
+                set_output(state, config, OutputConfig::Patch)?;
+                parse_slice(rest, state, config)
+            }
+            ["--print-lines", rest @ ..] => {
+                set_output(state, config, OutputConfig::Lines)?;
+                parse_slice(rest, state, config)
+            }
+            ["--count" | "-c", rest @ ..] => {
+                set_output(state, config, OutputConfig::Count)?;
+                parse_slice(rest, state, config)
+            }
+            ["--files-with-matches" | "-l", rest @ ..] => {
+                set_output(state, config, OutputConfig::FilesWithMatches)?;
+                parse_slice(rest, state, config)
+            }
+            ["--files-without-match", rest @ ..] => {
+                set_output(state, config, OutputConfig::FilesWithoutMatch)?;
+                parse_slice(rest, state, config)
+            }
+            ["--quiet" | "-q", rest @ ..] => {
+                config.quiet = true;
+                parse_slice(rest, state, config)
+            }
+            ["--first-parent", rest @ ..] => {
+                config.first_parent = true;
+                parse_slice(rest, state, config)
+            }
+            ["--show-refs", rest @ ..] => {
+                config.show_refs = true;
+                parse_slice(rest, state, config)
+            }
+            ["--unique", rest @ ..] => {
+                config.unique = true;
+                parse_slice(rest, state, config)
+            }
+            ["--quickfix", rest @ ..] => {
+                set_output(state, config, OutputConfig::Quickfix)?;
+                parse_slice(rest, state, config)
+            }
+            ["--print-json", rest @ ..] => {
+                set_output(state, config, OutputConfig::Json)?;
+                parse_slice(rest, state, config)
+            }
+            ["--print-jsonl", rest @ ..] => {
+                set_output(state, config, OutputConfig::JsonLines)?;
+                parse_slice(rest, state, config)
+            }
+            ["--regex", rest @ ..] => {
+                config.pattern_kind = PatternKind::Regex;
+                parse_slice(rest, state, config)
+            }
+            ["--ignore-case" | "-i", rest @ ..] => {
+                config.case_sensitivity = CaseSensitivity::Insensitive;
+                parse_slice(rest, state, config)
+            }
+            ["--smart-case", rest @ ..] => {
+                config.case_sensitivity = CaseSensitivity::Smart;
+                parse_slice(rest, state, config)
+            }
+            ["--invert-match" | "-v", rest @ ..] => {
+                config.invert_match = true;
+                parse_slice(rest, state, config)
+            }
+            ["-G", rest @ ..] => {
+                config.pickaxe = Pickaxe::LineMatch;
+                parse_slice(rest, state, config)
+            }
+            ["-S", rest @ ..] => {
+                config.pickaxe = Pickaxe::OccurrenceCount;
+                parse_slice(rest, state, config)
+            }
+            ["-e", pattern, rest @ ..] => {
+                if state.has_search_string && !state.has_pattern_option {
+                    bail!("Cannot use both PATTERN and -e. Run `hunk -h` for help");
                 }
+                state.pattern_tokens.push(PatternToken::Pattern(pattern.to_string()));
+                state.has_search_string = true;
+                state.has_pattern_option = true;
+                parse_slice(rest, state, config)
+            }
+            ["-e"] => Err(simple_error!("Expected argument for 'e'. Run `hunk -h` for help")),
+            ["--and", rest @ ..] => {
+                state.pattern_tokens.push(PatternToken::And);
+                parse_slice(rest, state, config)
+            }
+            ["--or", rest @ ..] => {
+                state.pattern_tokens.push(PatternToken::Or);
+                parse_slice(rest, state, config)
+            }
+            ["--not", rest @ ..] => {
+                state.pattern_tokens.push(PatternToken::Not);
+                parse_slice(rest, state, config)
+            }
+            ["--path", path, rest @ ..] => {
+                config.paths.push(path.to_string());
+                parse_slice(rest, state, config)
+            }
+            ["--path"] => Err(simple_error!("Expected argument for 'path'. Run `hunk -h` for help")),
+            ["--exclude-path", path, rest @ ..] => {
+                config.exclude_paths.push(path.to_string());
+                parse_slice(rest, state, config)
+            }
+            ["--exclude-path"] => Err(simple_error!("Expected argument for 'exclude-path'. Run `hunk -h` for help")),
+            ["--ref", ref_glob, rest @ ..] => {
+                config.refs.push(ref_glob.to_string());
+                parse_slice(rest, state, config)
+            }
+            ["--ref"] => Err(simple_error!("Expected argument for 'ref'. Run `hunk -h` for help")),
+            ["--tag", tag_glob, rest @ ..] => {
+                config.tags.push(tag_glob.to_string());
+                parse_slice(rest, state, config)
+            }
+            ["--tag"] => Err(simple_error!("Expected argument for 'tag'. Run `hunk -h` for help")),
+            ["--author", author, rest @ ..] => {
+                config.authors.push(author.to_string());
+                parse_slice(rest, state, config)
+            }
+            ["--author"] => Err(simple_error!("Expected argument for 'author'. Run `hunk -h` for help")),
+            ["--committer", committer, rest @ ..] => {
+                config.committers.push(committer.to_string());
+                parse_slice(rest, state, config)
+            }
+            ["--committer"] => Err(simple_error!("Expected argument for 'committer'. Run `hunk -h` for help")),
+            ["--since", since, rest @ ..] => {
+                config.since = Some(parse_date_arg(since)?.timestamp);
+                parse_slice(rest, state, config)
+            }
+            ["--since"] => Err(simple_error!("Expected argument for 'since'. Run `hunk -h` for help")),
+            ["--until", until, rest @ ..] => {
+                let date = parse_date_arg(until)?;
+                // A day on its own includes all of it
+                config.until = Some(if date.has_time { date.timestamp } else { date.timestamp + 86399 });
+                parse_slice(rest, state, config)
+            }
+            ["--until"] => Err(simple_error!("Expected argument for 'until'. Run `hunk -h` for help")),
+            ["--input", input, rest @ ..] => {
+                config.inputs.push(input.to_string());
+                parse_slice(rest, state, config)
+            }
+            ["--input"] => Err(simple_error!("Expected argument for 'input'. Run `hunk -h` for help")),
+            ["--scope", scope_str, rest @ ..] => {
+                config.scope = match scope_str {
+                    &"line" => MatchScope::Line,
+                    &"hunk" => MatchScope::Hunk,
+                    &"file" => MatchScope::File,
+                    &"patch" => MatchScope::Patch,
+                    other => bail!("Unknown value '{}'. Run `hunk -h` for help", other)
+                };
+                parse_slice(rest, state, config)
+            }
+            ["--scope"] => Err(simple_error!("Expected argument for 'scope'. Run `hunk -h` for help")),
+            ["--highlight", rest @ ..] => {
+                config.highlight = true;
+                parse_slice(rest, state, config)
+            }
+            ["--color", color_str, rest @ ..] => {
+                config.color = parse_color_choice(color_str)?;
+                parse_slice(rest, state, config)
+            }
+            ["--color"] => Err(simple_error!("Expected argument for 'color'. Run `hunk -h` for help")),
+            [arg, rest @ ..] if arg.starts_with("--color=") => {
+                config.color = parse_color_choice(&arg["--color=".len()..])?;
+                parse_slice(rest, state, config)
             }
             ["--invalid-utf8", decode_strategy_str, rest @ ..] => {
                 config.decode_strategy = match decode_strategy_str {
@@ -139,6 +545,16 @@ pub fn parse_args(args: &[&str]) -> Result<Config, SimpleError> {
                 parse_slice(rest, state, config)
             }
             ["--invalid-utf8"] => Err(simple_error!("Expected argument for 'invalid-utf8'. Run `hunk -h` for help")),
+            ["--granularity", granularity_str, rest @ ..] => {
+                config.granularity = match granularity_str {
+                    &"patch" => Granularity::Patch,
+                    &"file" => Granularity::File,
+                    &"hunk" => Granularity::Hunk,
+                    other => bail!("Unknown value '{}'. Run `hunk -h` for help", other)
+                };
+                parse_slice(rest, state, config)
+            }
+            ["--granularity"] => Err(simple_error!("Expected argument for 'granularity'. Run `hunk -h` for help")),
             ["--help"] | ["-h"] => {
                 print_help();
                 Err(simple_error!(""))
@@ -152,7 +568,11 @@ pub fn parse_args(args: &[&str]) -> Result<Config, SimpleError> {
                 state.has_search_string = true;
                 parse_slice(rest, state, config)
             }
-            [arg, ..] => Err(simple_error!("Unexpected arg: {}. Run `hunk -h` for help", arg)),
+            [arg, ..] if arg.starts_with('-') && *arg != "-" => Err(simple_error!("Unexpected arg: {}. Run `hunk -h` for help", arg)),
+            [input, rest @ ..] => {
+                config.inputs.push(input.to_string());
+                parse_slice(rest, state, config)
+            }
             [] if !state.has_search_string => Err(simple_error!("Expected a string to search for. Run `hunk -h` for help")),
             [] => Ok(())
         }
@@ -161,25 +581,54 @@ pub fn parse_args(args: &[&str]) -> Result<Config, SimpleError> {
     let mut config = Config {
         decode_strategy: UTF8Strategy::Panic,
         match_on: PatchSections {
-            diff: true,
+            added: true,
+            removed: true,
             context: false,
             file_header: false,
             patch_header: false,
         },
         output: OutputConfig::Sections(PatchSections{
-            diff: false,
+            added: false,
+            removed: false,
             context: false,
             file_header: false,
             patch_header: true,
         }),
         search_string: "".to_string(),
+        inputs: Vec::new(),
+        pattern_expr: None,
+        scope: MatchScope::Line,
+        paths: Vec::new(),
+        exclude_paths: Vec::new(),
+        refs: Vec::new(),
+        tags: Vec::new(),
+        authors: Vec::new(),
+        committers: Vec::new(),
+        since: None,
+        until: None,
+        pattern_kind: PatternKind::Fixed,
+        case_sensitivity: CaseSensitivity::Sensitive,
+        pickaxe: Pickaxe::LineMatch,
+        invert_match: false,
+        first_parent: false,
+        quiet: false,
+        show_refs: false,
+        unique: false,
+        highlight: false,
+        color: ColorChoice::Auto,
+        granularity: Granularity::Patch,
     };
     let mut parsing_state = ParsingState {
         has_search_string: false,
         no_more_options: false,
         has_print_option: false,
+        has_pattern_option: false,
+        pattern_tokens: Vec::new(),
     };
     parse_slice(args, &mut parsing_state, &mut config)?;
+    if !parsing_state.pattern_tokens.is_empty() {
+        config.pattern_expr = Some(build_pattern_expr(&parsing_state.pattern_tokens)?);
+    }
 
     Ok(config)
 }
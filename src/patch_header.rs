@@ -0,0 +1,250 @@
+use std::fmt;
+
+/// The fields of a `git log` commit header, or of the email headers that
+/// `git format-patch` writes. The lines they were parsed from are kept in the
+/// patch to be printed as they were.
+#[derive(PartialEq, Debug, Default)]
+pub struct PatchHeader {
+    pub commit: Option<String>,
+    /// The parent of a merge that the diff is against, which `git log -m`
+    /// shows as `(from <parent>)` after the hash
+    pub parent: Option<String>,
+    /// The branches and tags that `git log --decorate` shows after the hash
+    pub refs: Vec<CommitRef>,
+    /// The abbreviated hashes of the parents on the `Merge:` line of a merge
+    pub merge_parents: Vec<String>,
+    /// The name and email, as in `Name <email>`
+    pub author: Option<String>,
+    /// The author date, in whichever format `git log --date` produced
+    pub date: Option<String>,
+    /// Only shown by `git log --format=fuller`
+    pub committer: Option<String>,
+    /// Only shown by `git log --format=fuller`
+    pub commit_date: Option<String>,
+    /// The commit message without its indentation, including any trailers
+    pub message: Option<String>,
+    pub trailers: Vec<Trailer>,
+}
+
+/// A `Key: value` line at the end of a commit message, such as
+/// `Signed-off-by: Name <email>`
+#[derive(PartialEq, Debug, Clone)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
+}
+
+/// A branch, tag or `HEAD` that points at a commit
+#[derive(PartialEq, Debug, Clone)]
+pub struct CommitRef {
+    /// The name without the `refs/heads/`, `refs/tags/` or `refs/remotes/`
+    /// that `--decorate=full` adds, so `main`, `origin/main`, `v1.0` or `HEAD`
+    pub name: String,
+    pub is_tag: bool,
+}
+
+/// Formats the ref the way `git log --decorate` does
+impl fmt::Display for CommitRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_tag {
+            write!(f, "tag: {}", self.name)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+
+/// The refs as a comma-separated list, or an empty string if there are none
+pub fn format_refs(refs: &[CommitRef]) -> String {
+    refs.iter().map(CommitRef::to_string).collect::<Vec<String>>().join(", ")
+}
+
+fn short_ref_name(name: &str) -> String {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"].iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// Parse the refs in a `(HEAD -> main, tag: v1.0, origin/main)` decoration
+fn parse_decoration(decoration: &str) -> Vec<CommitRef> {
+    let mut refs = Vec::new();
+    for item in decoration.split(", ") {
+        if let Some(tag) = item.strip_prefix("tag: ") {
+            refs.push(CommitRef { name: short_ref_name(tag), is_tag: true });
+            continue;
+        }
+        // `HEAD -> main` is HEAD pointing at the branch that's checked out
+        for name in item.split(" -> ") {
+            refs.push(CommitRef { name: short_ref_name(name), is_tag: false });
+        }
+    }
+    refs
+}
+
+/// The parts of the `commit` line that starts each commit in `git log` output,
+/// `commit <hash> [(from <parent>)] [(<refs>)]`
+#[derive(PartialEq, Debug)]
+pub struct CommitLine<'a> {
+    pub hash: &'a str,
+    /// `git log -m` shows a merge once for each of its parents, with the one
+    /// that the diff is against in `(from <parent>)`
+    pub parent: Option<&'a str>,
+    pub refs: Vec<CommitRef>,
+}
+
+pub fn parse_commit_line(line: &str) -> Option<CommitLine<'_>> {
+    let rest = line.strip_prefix("commit ")?;
+    let (hash, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let mut parent = None;
+    if let Some((from, after)) = rest.strip_prefix("(from ").and_then(|rest| rest.split_once(')')) {
+        parent = Some(from);
+        rest = after.trim_start();
+    }
+    let refs = match rest.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')) {
+        Some(decoration) => parse_decoration(decoration),
+        None => Vec::new(),
+    };
+    Some(CommitLine { hash, parent, refs })
+}
+
+/// The commit hash from the `From <sha> Mon Sep 17 00:00:00 2001` line that
+/// starts each patch in `git format-patch` output and mbox files
+pub fn mbox_from_line_commit(line: &str) -> Option<&str> {
+    let (commit, date) = line.strip_prefix("From ")?.split_once(' ')?;
+    let is_hash = commit.len() >= 7 && commit.chars().all(|c| c.is_ascii_hexdigit());
+    (is_hash && !date.is_empty()).then_some(commit)
+}
+
+/// Remove the `[PATCH n/m]` prefix from an email subject
+fn strip_subject_prefix(subject: &str) -> &str {
+    match subject.strip_prefix('[').and_then(|rest| rest.split_once("] ")) {
+        Some((_, rest)) => rest,
+        None => subject,
+    }
+}
+
+/// The trailers in the last paragraph of a message, if every line of it is
+/// one. Like `git interpret-trailers`, a line that starts with whitespace
+/// continues the trailer before it, and the subject is never a trailer.
+fn parse_trailers(message: &str) -> Vec<Trailer> {
+    let Some((_, last_paragraph)) = message.rsplit_once("\n\n") else {
+        return Vec::new();
+    };
+    let mut trailers: Vec<Trailer> = Vec::new();
+    for line in last_paragraph.lines() {
+        if line.starts_with([' ', '\t']) {
+            let Some(trailer) = trailers.last_mut() else {
+                return Vec::new();
+            };
+            trailer.value.push(' ');
+            trailer.value.push_str(line.trim());
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            return Vec::new();
+        };
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Vec::new();
+        }
+        trailers.push(Trailer {
+            key: key.to_string(),
+            value: value.trim().to_string(),
+        });
+    }
+    trailers
+}
+
+fn join_message(mut message_lines: Vec<&str>) -> Option<String> {
+    while message_lines.last().is_some_and(|line| line.trim().is_empty()) {
+        message_lines.pop();
+    }
+    (!message_lines.is_empty()).then(|| message_lines.join("\n"))
+}
+
+/// Parse the email headers and message of a `git format-patch` patch. The
+/// message is the subject followed by the body, which ends at the `---` line
+/// before the diffstat.
+fn parse_mbox_header(lines: &[String]) -> PatchHeader {
+    let mut header = PatchHeader {
+        commit: lines.first().and_then(|line| mbox_from_line_commit(line)).map(str::to_string),
+        ..PatchHeader::default()
+    };
+    let mut subject = String::new();
+    let mut in_subject = false;
+    let mut body_lines: Vec<&str> = Vec::new();
+    let mut in_body = false;
+    for line in lines.iter().skip(1) {
+        if in_body {
+            if line == "---" {
+                break;
+            }
+            body_lines.push(line);
+        } else if in_subject && line.starts_with([' ', '\t']) {
+            // Long subjects are folded onto more than one line
+            subject.push_str(line.trim_end());
+        } else if let Some(rest) = line.strip_prefix("Subject: ") {
+            subject = rest.to_string();
+            in_subject = true;
+        } else if let Some(author) = line.strip_prefix("From: ") {
+            header.author = Some(author.trim().to_string());
+            in_subject = false;
+        } else if let Some(date) = line.strip_prefix("Date: ") {
+            header.date = Some(date.trim().to_string());
+            in_subject = false;
+        } else if line.is_empty() {
+            in_body = true;
+        } else {
+            in_subject = false;
+        }
+    }
+    let subject = strip_subject_prefix(&subject);
+    let mut message_lines = vec![subject];
+    if body_lines.iter().any(|line| !line.trim().is_empty()) {
+        message_lines.push("");
+        message_lines.extend(body_lines.iter().skip_while(|line| line.trim().is_empty()));
+    }
+    header.message = join_message(message_lines);
+    header.trailers = header.message.as_deref().map(parse_trailers).unwrap_or_default();
+    header
+}
+
+/// Parse the lines of a patch header, which have already had their ANSI codes
+/// and line endings removed
+pub fn parse_patch_header(lines: &[String]) -> PatchHeader {
+    if lines.first().is_some_and(|line| mbox_from_line_commit(line).is_some()) {
+        return parse_mbox_header(lines);
+    }
+    let mut header = PatchHeader::default();
+    let mut message_lines: Vec<&str> = Vec::new();
+    let mut in_message = false;
+    for line in lines {
+        if in_message {
+            // git indents every line of the message by 4 spaces
+            if let Some(message_line) = line.strip_prefix("    ") {
+                message_lines.push(message_line);
+            } else if line.is_empty() {
+                message_lines.push("");
+            }
+        } else if let Some(commit_line) = parse_commit_line(line) {
+            header.commit = Some(commit_line.hash.to_string());
+            header.parent = commit_line.parent.map(str::to_string);
+            header.refs = commit_line.refs;
+        } else if let Some(parents) = line.strip_prefix("Merge:") {
+            header.merge_parents = parents.split_whitespace().map(str::to_string).collect();
+        } else if let Some(author) = line.strip_prefix("Author:") {
+            header.author = Some(author.trim().to_string());
+        } else if let Some(date) = line.strip_prefix("Date:").or(line.strip_prefix("AuthorDate:")) {
+            header.date = Some(date.trim().to_string());
+        } else if let Some(committer) = line.strip_prefix("Commit:") {
+            header.committer = Some(committer.trim().to_string());
+        } else if let Some(date) = line.strip_prefix("CommitDate:") {
+            header.commit_date = Some(date.trim().to_string());
+        } else if line.is_empty() {
+            in_message = true;
+        }
+    }
+    header.message = join_message(message_lines);
+    header.trailers = header.message.as_deref().map(parse_trailers).unwrap_or_default();
+    header
+}
@@ -0,0 +1,69 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use simple_error::{SimpleError, simple_error};
+
+use crate::{file_paths::FilePaths, parse_args::Config};
+
+/// The compiled form of the `--path` and `--exclude-path` globs in a `Config`
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+/// Globs follow git's `:(glob)` pathspec magic: `*` does not match `/`, while
+/// `**` matches any number of directories.
+fn build_glob(pattern: &str) -> Result<Glob, SimpleError> {
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    let pattern = pattern.strip_suffix('/').unwrap_or(pattern);
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|err| simple_error!("Invalid path glob '{}': {}", pattern, err))
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, SimpleError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(build_glob(pattern)?);
+    }
+    builder.build().map_err(|err| simple_error!("Invalid path glob: {}", err))
+}
+
+/// Like a pathspec, a glob that matches a directory matches everything in it
+fn matches_path_or_parent(glob_set: &GlobSet, path: &str) -> bool {
+    let mut prefix_end = Some(path.len());
+    while let Some(end) = prefix_end {
+        if glob_set.is_match(&path[..end]) {
+            return true;
+        }
+        prefix_end = path[..end].rfind('/');
+    }
+    false
+}
+
+impl PathFilter {
+    pub fn new(config: &Config) -> Result<PathFilter, SimpleError> {
+        Ok(PathFilter {
+            include: if config.paths.is_empty() {
+                None
+            } else {
+                Some(build_glob_set(&config.paths)?)
+            },
+            exclude: build_glob_set(&config.exclude_paths)?,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.include.is_some() || !self.exclude.is_empty()
+    }
+
+    /// Whether a file takes part in matching and printing. A renamed file is
+    /// included if either of its paths is.
+    pub fn includes(&self, paths: &FilePaths) -> bool {
+        let paths: Vec<&String> = paths.old.iter().chain(paths.new.iter()).collect();
+        let included = match &self.include {
+            Some(include) => paths.iter().any(|path| matches_path_or_parent(include, path)),
+            None => true,
+        };
+        included && !paths.iter().any(|path| matches_path_or_parent(&self.exclude, path))
+    }
+}
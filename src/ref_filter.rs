@@ -0,0 +1,49 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use simple_error::{SimpleError, simple_error};
+
+use crate::{parse_args::Config, patch_header::CommitRef};
+
+/// The compiled form of the `--ref` and `--tag` globs in a `Config`
+pub struct RefFilter {
+    refs: Option<GlobSet>,
+    tags: Option<GlobSet>,
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<Option<GlobSet>, SimpleError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|err| simple_error!("Invalid ref glob '{}': {}", pattern, err))?);
+    }
+    builder.build()
+        .map(Some)
+        .map_err(|err| simple_error!("Invalid ref glob: {}", err))
+}
+
+impl RefFilter {
+    pub fn new(config: &Config) -> Result<RefFilter, SimpleError> {
+        Ok(RefFilter {
+            refs: build_glob_set(&config.refs)?,
+            tags: build_glob_set(&config.tags)?,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.refs.is_some() || self.tags.is_some()
+    }
+
+    /// Whether a commit with these refs takes part in matching. With globs,
+    /// it does if any of them match a `--ref` glob, or any of its tags match
+    /// a `--tag` glob.
+    pub fn includes(&self, refs: &[CommitRef]) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+        refs.iter().any(|commit_ref| {
+            self.refs.as_ref().is_some_and(|globs| globs.is_match(&commit_ref.name))
+                || (commit_ref.is_tag && self.tags.as_ref().is_some_and(|globs| globs.is_match(&commit_ref.name)))
+        })
+    }
+}
@@ -1,18 +1,33 @@
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
-    use crate::{parse_args::{parse_args, UTF8Strategy, Config, PatchSections, OutputConfig}, process_lines};
-    use std::{fs, io::BufReader};
+    use crate::{
+        date::{parse_date, Date},
+        file_paths::{parse_file_paths, FilePaths},
+        highlight::highlight_line,
+        input::{input_sources, InputSource},
+        hunk_header::{hunk_parent_count, parse_hunk_header, HunkHeader, HunkRange},
+        parse_args::{
+            parse_args, UTF8Strategy, CaseSensitivity, ColorChoice, Config, Granularity, MatchScope, PatchSections, PatternExpr,
+            PatternKind, Pickaxe, OutputConfig
+        },
+        patch_header::{format_refs, parse_commit_line, parse_patch_header, CommitLine, CommitRef, PatchHeader, Trailer},
+        is_broken_pipe, process_inputs, process_lines,
+    };
+    use std::{fs, io::{self, BufReader}};
 
     const PATCH_SECTIONS_ALL: PatchSections = PatchSections {
         context: true,
-        diff: true,
+        added: true,
+        removed: true,
         file_header: true,
         patch_header: true
     };
 
     const PATCH_SECTIONS_NONE: PatchSections = PatchSections {
         context: false,
-        diff: false,
+        added: false,
+        removed: false,
         file_header: false,
         patch_header: false
     };
@@ -27,9 +42,11 @@ mod tests {
         let mut out_vec: Vec<u8> = Vec::new();
         let mut config = Config::default();
         config.search_string = "AIPlayer".to_string();
-        config.match_on.diff = true;
+        config.match_on.added = true;
+        config.match_on.removed = true;
         let mut output_sections = PATCH_SECTIONS_NONE;
-        output_sections.diff = true;
+        output_sections.added = true;
+        output_sections.removed = true;
         config.output = OutputConfig::Sections(output_sections);
         process_lines(
             Box::new(BufReader::new(file)),
@@ -47,9 +64,11 @@ mod tests {
         let mut out_vec: Vec<u8> = Vec::new();
         let mut config = Config::default();
         config.search_string = "AIPlayer".to_string();
-        config.match_on.diff = true;
+        config.match_on.added = true;
+        config.match_on.removed = true;
         let mut output_sections = PATCH_SECTIONS_NONE;
-        output_sections.diff = true;
+        output_sections.added = true;
+        output_sections.removed = true;
         config.output = OutputConfig::Sections(output_sections);
         process_lines(
             Box::new(BufReader::new(file)),
@@ -67,7 +86,8 @@ mod tests {
         let mut out_vec: Vec<u8> = Vec::new();
         let mut config = Config::default();
         config.search_string = "AIPlayer".to_string();
-        config.match_on.diff = true;
+        config.match_on.added = true;
+        config.match_on.removed = true;
         config.output = OutputConfig::CommitHash;
         process_lines(
             Box::new(BufReader::new(file)),
@@ -81,7 +101,7 @@ mod tests {
         assert!(out_lines[0] == "bcd581d22a277d2f7e8766219f96412f516418af");
         assert!(out_lines[1] == "39512adde34a5ece411a7ef67a363fa33a333f45");
         assert!(out_lines[2] == "a9b7171d2eb0164592e20e39d9f126412a44964f");
-        assert!(out_lines[3] == "");
+        assert!(out_lines[3].is_empty());
 
     }
 
@@ -92,9 +112,11 @@ mod tests {
         let mut out_vec: Vec<u8> = Vec::new();
         let mut config = Config::default();
         config.search_string = "修复安装包许可协议乱码问题".to_string();
-        config.match_on.diff = true;
+        config.match_on.added = true;
+        config.match_on.removed = true;
         let mut output_sections = PATCH_SECTIONS_NONE;
-        output_sections.diff = true;
+        output_sections.added = true;
+        output_sections.removed = true;
         config.output = OutputConfig::Sections(output_sections);
         process_lines(
             Box::new(BufReader::new(file)),
@@ -116,7 +138,7 @@ mod tests {
             Box::new(BufReader::new(file)),
             Box::new(&mut out_vec),
             &config
-        ).err().expect("");
+        ).expect_err("");
     }
 
     #[test]
@@ -144,9 +166,11 @@ mod tests {
         let mut config = Config::default();
         config.search_string = "fg".to_string();
         config.decode_strategy = UTF8Strategy::Lossy;
-        config.match_on.diff = true;
+        config.match_on.added = true;
+        config.match_on.removed = true;
         let mut output_sections = PATCH_SECTIONS_NONE;
-        output_sections.diff = true;
+        output_sections.added = true;
+        output_sections.removed = true;
         config.output = OutputConfig::Sections(output_sections);
         process_lines(
             Box::new(BufReader::new(file)),
@@ -168,7 +192,7 @@ mod tests {
             Box::new(BufReader::new(file)),
             Box::new(&mut out_vec),
             &config
-        ).err().expect("");
+        ).expect_err("");
     }
 
     #[test]
@@ -196,9 +220,11 @@ mod tests {
         let mut config = Config::default();
         config.search_string = "Invalid".to_string();
         config.decode_strategy = UTF8Strategy::Lossy;
-        config.match_on.diff = true;
+        config.match_on.added = true;
+        config.match_on.removed = true;
         let mut output_sections = PATCH_SECTIONS_NONE;
-        output_sections.diff = true;
+        output_sections.added = true;
+        output_sections.removed = true;
         config.output = OutputConfig::Sections(output_sections);
         process_lines(
             Box::new(BufReader::new(file)),
@@ -255,9 +281,11 @@ mod tests {
         let mut out_vec: Vec<u8> = Vec::new();
         let mut config = Config::default();
         config.search_string = "console.log(w, l, m);".to_string();
-        config.match_on.diff = true;
+        config.match_on.added = true;
+        config.match_on.removed = true;
         let mut output_sections = PATCH_SECTIONS_NONE;
-        output_sections.diff = true;
+        output_sections.added = true;
+        output_sections.removed = true;
         config.output = OutputConfig::Sections(output_sections);
         process_lines(
             Box::new(BufReader::new(file)),
@@ -313,9 +341,11 @@ mod tests {
         let mut out_vec: Vec<u8> = Vec::new();
         let mut config = Config::default();
         config.search_string = "hi".to_string();
-        config.match_on.diff = true;
+        config.match_on.added = true;
+        config.match_on.removed = true;
         let mut output_sections = PATCH_SECTIONS_NONE;
-        output_sections.diff = true;
+        output_sections.added = true;
+        output_sections.removed = true;
         config.output = OutputConfig::Sections(output_sections);
         process_lines(
             Box::new(BufReader::new(file)),
@@ -328,104 +358,2455 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_args() {
-        let config = parse_args(&vec!["asd"]).unwrap();
-        assert_eq!(config.search_string, "asd");
+    fn test_no_commit_header() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("+    black: AIPlayer;"));
+        // the mode-only change gets its own file header
+        assert!(out_str.contains("old mode 100644\nnew mode 100755\ndiff --git a/src/game.ts"));
     }
 
     #[test]
-    fn test_parse_extra_positional() {
-        expect_err(parse_args(&vec!["asd", "qwe"]));
+    fn test_no_commit_header_commit_hash() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::CommitHash;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.is_empty());
     }
 
     #[test]
-    fn test_parse_extra_positional_explicit() {
-        expect_err(parse_args(&vec!["asd", "--", "qwe"]));
+    fn test_no_commit_header_invalid_start() {
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        process_lines(
+            Box::new("Not a patch\n".as_bytes()),
+            Box::new(&mut out_vec),
+            &config
+        ).expect_err("");
     }
 
     #[test]
-    fn test_parse_trailing_explicit() {
-        expect_err(parse_args(&vec!["asd", "--"]));
+    fn test_granularity_hunk() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        config.granularity = Granularity::Hunk;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("+The AIPlayer always plays black."));
+        assert!(out_str.contains("+    black: AIPlayer;"));
+        assert!(out_str.contains("diff --git a/src/game.ts"));
+        assert!(!out_str.contains("game started"));
+        assert!(!out_str.contains("game finished"));
+        assert!(!out_str.contains("run.sh"));
     }
 
     #[test]
-    fn test_parse_explicit() {
-        let config = parse_args(&vec!["--", "asd"]).unwrap();
-        assert!(config.search_string == "asd");
+    fn test_granularity_file() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "game started".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        config.granularity = Granularity::File;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("game started"));
+        assert!(out_str.contains("game finished"));
+        assert!(out_str.contains("+    black: AIPlayer;"));
+        assert!(!out_str.contains("docs/notes.txt"));
+        assert!(!out_str.contains("run.sh"));
     }
 
     #[test]
-    fn test_parse_explicit_flag_like() {
-        let config = parse_args(&vec!["--", "-h"]).unwrap();
-        assert!(config.search_string == "-h");
+    fn test_granularity_hunk_file_header_match() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "src/game.ts".to_string();
+        config.match_on.file_header = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        config.granularity = Granularity::Hunk;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // a file header match selects every hunk in the file
+        assert!(out_str.contains("game started"));
+        assert!(out_str.contains("+    black: AIPlayer;"));
+        assert!(!out_str.contains("docs/notes.txt"));
     }
-    
+
     #[test]
-    fn test_parse_explicit_duplicate_flag_like() {
-        let config = parse_args(&vec!["--match-fields", "diff", "--", "-h"]).unwrap();
-        assert!(config.search_string == "-h");
+    fn test_hunk_order_preserved() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("-    black: Player;\n+    black: AIPlayer;\n \n-    constructor"));
     }
-    
+
     #[test]
-    fn test_parse_explicit_duplicate_flag_like_with_arg() {
-        let config = parse_args(&vec!["--match-fields", "diff", "--", "--match-fields"]).unwrap();
-        assert!(config.search_string == "--match-fields");
+    fn test_print_patch_partial_hunk() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "black: AIPlayer;".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Patch;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.starts_with("diff --git a/src/game.ts b/src/game.ts\n"));
+        assert!(out_str.contains("@@ -1,12 +1,12 @@\n"));
+        // removals that didn't match become context, additions are dropped
+        assert!(out_str.contains("\n     constructor(white: Player, black: Player) {\n"));
+        assert!(!out_str.contains("import { AIPlayer }"));
+        assert!(!out_str.contains("@@ -14,6"));
     }
 
     #[test]
-    fn test_parse_no_program_name() {
-        expect_err(parse_args(&vec![]));
+    fn test_print_patch_offset() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "game finished".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Patch;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // the new side no longer includes the lines added by earlier hunks
+        assert!(out_str.contains("@@ -21,5 +21,6 @@ export class Game {\n"));
+        assert!(!out_str.contains("game started"));
     }
 
     #[test]
-    fn test_parse_match_fields() {
-        let config = parse_args(&vec!["asd", "--match-fields", "diff,context"]).unwrap();
-        assert_eq!(config.search_string, "asd");
-        assert_eq!(config.match_on.diff, true);
-        assert_eq!(config.match_on.context, true);
-        assert_eq!(config.match_on.file_header, false);
-        assert_eq!(config.match_on.patch_header, false);
+    fn test_print_patch_color() {
+        let file = fs::File::open("test_data/1_color.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Patch;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains(&config.search_string));
+        assert!(!out_str.contains('\x1b'));
     }
 
     #[test]
-    fn test_parse_match_fields_repeat_positional_after() {
-        expect_err(parse_args(&vec!["asd", "--match-fields", "diff,context", "qwe"]));
+    fn test_parse_hunk_header() {
+        let header = parse_hunk_header("@@ -2 +2,4 @@ Notes").unwrap();
+        assert_eq!(header, HunkHeader {
+            old: HunkRange { start: 2, len: 1 },
+            other_parents: Vec::new(),
+            new: HunkRange { start: 2, len: 4 },
+            section: " Notes".to_string(),
+        });
+        assert_eq!(header.to_string(), "@@ -2 +2,4 @@ Notes");
+        expect_err(parse_hunk_header("@@ -2 @@"));
     }
 
     #[test]
-    fn test_parse_match_on_invalid() {
-        expect_err(parse_args(&vec!["asd", "--match-on", "qwe"]));
+    fn test_parse_combined_hunk_header() {
+        let header = parse_hunk_header("@@@ -2,6 -2 +2,6 @@@ export class Game").unwrap();
+        assert_eq!(header, HunkHeader {
+            old: HunkRange { start: 2, len: 6 },
+            other_parents: vec![HunkRange { start: 2, len: 1 }],
+            new: HunkRange { start: 2, len: 6 },
+            section: " export class Game".to_string(),
+        });
+        assert_eq!(header.to_string(), "@@@ -2,6 -2 +2,6 @@@ export class Game");
+        assert_eq!(hunk_parent_count("@@@@ -1 -1 -1 +1 @@@@"), 3);
+        assert_eq!(hunk_parent_count("@@ -1 +1 @@"), 1);
+        // An octopus merge header needs a range for every parent
+        expect_err(parse_hunk_header("@@@@ -1 -1 +1 @@@@"));
+        expect_err(parse_hunk_header("@@@ -1 -1 +1 @@"));
     }
 
     #[test]
-    fn test_parse_print_fields_commit() {
-        expect_err(parse_args(&vec!["asd", "--print-fields", "diff,context", "--print-commits"]));
+    fn test_regex_anchor() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = r"^\s+this\.board\.log\(".to_string();
+        config.pattern_kind = PatternKind::Regex;
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        config.granularity = Granularity::Hunk;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("game started"));
+        assert!(out_str.contains("game finished"));
+        assert!(!out_str.contains("AIPlayer"));
     }
 
     #[test]
-    fn test_parse_commit_print_fields() {
-        expect_err(parse_args(&vec!["asd", "--print-commits", "--print-fields", "diff,context"]));
+    fn test_fixed_string_with_prefix() {
+        // Fixed strings are searched for in the whole line, including its
+        // leading '+', '-' or ' ', unlike regular expressions
+        let run = |pattern: &str, pattern_kind: PatternKind| -> String {
+            let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = pattern.to_string();
+            config.pattern_kind = pattern_kind;
+            config.match_on.added = true;
+            config.match_on.removed = true;
+            config.output = OutputConfig::Lines;
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            String::from_utf8(out_vec).unwrap()
+        };
+        assert_eq!(run("+    black", PatternKind::Fixed), "src/game.ts:8:    black: AIPlayer;\n");
+        assert_eq!(run(r"\+\s+black", PatternKind::Regex), "");
     }
 
     #[test]
-    fn test_parse_utf8_invalid() {
-        expect_err(parse_args(&vec!["asd", "--invalid-utf8", "qwe"]));
+    fn test_regex_alternation() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = r#"(always|"\./[a-z]+") plays?"#.to_string();
+        config.pattern_kind = PatternKind::Regex;
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        config.granularity = Granularity::Hunk;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("+The AIPlayer always plays black."));
+        assert!(!out_str.contains("src/game.ts"));
     }
 
     #[test]
-    fn test_parse_utf8_missing() {
-        expect_err(parse_args(&vec!["asd", "--invalid-utf8"]));
+    fn test_regex_invalid() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "(AIPlayer".to_string();
+        config.pattern_kind = PatternKind::Regex;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).expect_err("");
     }
 
     #[test]
-    fn test_parse_utf8_valid() {
-        let config = parse_args(&vec!["asd", "--invalid-utf8", "skip-line"]).unwrap();
-        assert_eq!(config.search_string, "asd");
-        assert_eq!(config.decode_strategy, UTF8Strategy::SkipLine);
+    fn test_fixed_string_special_chars() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "notify(".to_string();
+        config.match_on.context = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("this.white.notify(\"start\");"));
+    }
+
+    const UNICODE_CASE_DIFF: &str = "\
+diff --git a/names.txt b/names.txt
+--- a/names.txt
++++ b/names.txt
+@@ -1 +1 @@
+-Sisyphus
++ΣΊΣΥΦΟΣ
+";
+
+    #[test]
+    fn test_ignore_case() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "aiplayer".to_string();
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("AIPlayer"));
+    }
+
+    #[test]
+    fn test_ignore_case_unicode() {
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "σίσυφος".to_string();
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(UNICODE_CASE_DIFF.as_bytes()),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("+ΣΊΣΥΦΟΣ"));
+    }
+
+    #[test]
+    fn test_ignore_case_cjk() {
+        let file = fs::File::open("test_data/unicode_chars_CJK.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "修复安装包许可协议乱码问题".to_string();
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        let mut output_sections = PATCH_SECTIONS_NONE;
+        output_sections.added = true;
+        output_sections.removed = true;
+        config.output = OutputConfig::Sections(output_sections);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains(&config.search_string));
+    }
+
+    #[test]
+    fn test_smart_case() {
+        let run = |search_string: &str, pattern_kind: PatternKind| -> String {
+            let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = search_string.to_string();
+            config.pattern_kind = pattern_kind;
+            config.case_sensitivity = CaseSensitivity::Smart;
+            config.match_on.added = true;
+            config.match_on.removed = true;
+            config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            String::from_utf8(out_vec).unwrap()
+        };
+        assert!(run("aiplayer", PatternKind::Fixed).contains("AIPlayer"));
+        assert!(run("AiPlayer", PatternKind::Fixed).is_empty());
+        // escapes don't count as uppercase characters
+        assert!(run(r"aiplayer\S", PatternKind::Regex).contains("AIPlayer"));
+        assert!(run(r"\p{Lu}iplayer", PatternKind::Regex).contains("AIPlayer"));
+        assert!(run(r"AiPlayer\S", PatternKind::Regex).is_empty());
+    }
+
+    #[test]
+    fn test_match_added_only() {
+        let run = |search_string: &str, added: bool, removed: bool| -> String {
+            let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = search_string.to_string();
+            config.match_on.added = added;
+            config.match_on.removed = removed;
+            config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            String::from_utf8(out_vec).unwrap()
+        };
+        // only appears on a removed line
+        assert!(run("black: Player;", true, false).is_empty());
+        assert!(!run("black: Player;", false, true).is_empty());
+        // only appears on an added line
+        assert!(!run("black: AIPlayer;", true, false).is_empty());
+        assert!(run("black: AIPlayer;", false, true).is_empty());
+    }
+
+    #[test]
+    fn test_print_removed_only() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        let mut output_sections = PATCH_SECTIONS_NONE;
+        output_sections.removed = true;
+        config.output = OutputConfig::Sections(output_sections);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "-    black: Player;\n-    constructor(white: Player, black: Player) {\n");
+    }
+
+    const MOVED_LINE_DIFF: &str = "\
+diff --git a/src/player.ts b/src/player.ts
+--- a/src/player.ts
++++ b/src/player.ts
+@@ -1,6 +1,6 @@
+-import { AIPlayer } from \"./ai\";
+ import { Board } from \"./board\";
++    import { AIPlayer } from \"./ai\";
+ 
+ export class Player {
+     name: string;
+";
+
+    #[test]
+    fn test_pickaxe_occurrence_count() {
+        let run = |search_string: &str, pickaxe: Pickaxe| -> String {
+            let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = search_string.to_string();
+            config.pickaxe = pickaxe;
+            config.match_on.added = true;
+            config.match_on.removed = true;
+            config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+            config.granularity = Granularity::File;
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            String::from_utf8(out_vec).unwrap()
+        };
+        // "black" is removed and added the same number of times in src/game.ts
+        let out_str = run("black", Pickaxe::OccurrenceCount);
+        println!("{}", out_str);
+        assert!(out_str.contains("docs/notes.txt"));
+        assert!(!out_str.contains("src/game.ts"));
+        let out_str = run("black", Pickaxe::LineMatch);
+        assert!(out_str.contains("docs/notes.txt"));
+        assert!(out_str.contains("src/game.ts"));
+    }
+
+    #[test]
+    fn test_pickaxe_moved_line() {
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.pickaxe = Pickaxe::OccurrenceCount;
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(MOVED_LINE_DIFF.as_bytes()),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.is_empty());
+    }
+
+    const ALL_FIELDS: &str = "patch_header,file_header,context,diff";
+
+    /// Run the arguments that would follow `hunk` on test_data/no_commit_header.diff
+    fn run_args(args: &[&str]) -> String {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let config = parse_args(args).unwrap();
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        out_str
+    }
+
+    #[test]
+    fn test_pattern_and_line_scope() {
+        let out_str = run_args(&["--print-fields", ALL_FIELDS, "--granularity", "hunk", "--scope", "line", "-e", "AIPlayer", "--and", "-e", "constructor"]);
+        assert!(out_str.contains("+    constructor(white: Player, black: AIPlayer) {"));
+        assert!(!out_str.contains("docs/notes.txt"));
+        assert!(!out_str.contains("game started"));
+    }
+
+    #[test]
+    fn test_pattern_and_hunk_scope() {
+        let out_str = run_args(&["--print-fields", ALL_FIELDS, "--granularity", "hunk", "--scope", "hunk", "-e", "game started", "--and", "-e", "AIPlayer"]);
+        assert!(out_str.is_empty());
+        let out_str = run_args(&["--print-fields", ALL_FIELDS, "--granularity", "hunk", "--scope", "hunk", "-e", "black", "--and", "-e", "AIPlayer"]);
+        assert!(out_str.contains("+    black: AIPlayer;"));
+        assert!(out_str.contains("+The AIPlayer always plays black."));
+        assert!(!out_str.contains("game started"));
+    }
+
+    #[test]
+    fn test_pattern_and_file_scope() {
+        let out_str = run_args(&["--print-fields", ALL_FIELDS, "--granularity", "hunk", "--scope", "file", "-e", "game started", "--and", "-e", "AIPlayer"]);
+        // only the hunks containing one of the patterns are printed
+        assert!(out_str.contains("+    black: AIPlayer;"));
+        assert!(out_str.contains("game started"));
+        assert!(!out_str.contains("game finished"));
+        assert!(!out_str.contains("docs/notes.txt"));
+    }
+
+    #[test]
+    fn test_pattern_not() {
+        let out_str = run_args(&["--print-fields", ALL_FIELDS, "--granularity", "hunk", "--scope", "line", "-e", "AIPlayer", "--and", "--not", "-e", "constructor"]);
+        assert!(out_str.contains("+    black: AIPlayer;"));
+        assert!(out_str.contains("+The AIPlayer always plays black."));
+        let out_str = run_args(&["--print-fields", ALL_FIELDS, "--granularity", "hunk", "--scope", "patch", "-e", "board", "--and", "--not", "-e", "AIPlayer"]);
+        assert!(out_str.is_empty());
+        let out_str = run_args(&["--print-fields", ALL_FIELDS, "--granularity", "hunk", "--scope", "hunk", "-e", "board", "--and", "--not", "-e", "AIPlayer"]);
+        assert!(out_str.contains("game started"));
+        assert!(out_str.contains("game finished"));
+        assert!(!out_str.contains("AIPlayer"));
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let out_str = run_args(&["--match-fields", ALL_FIELDS, "--print-fields", ALL_FIELDS, "--path", "src", "a"]);
+        assert!(out_str.contains("diff --git a/src/game.ts"));
+        assert!(!out_str.contains("docs/notes.txt"));
+        assert!(!out_str.contains("run.sh"));
+        let out_str = run_args(&["--match-fields", ALL_FIELDS, "--print-fields", ALL_FIELDS, "--path", "*.sh", "--path", "docs/", "a"]);
+        assert!(!out_str.contains("src/game.ts"));
+        assert!(out_str.contains("diff --git a/docs/notes.txt"));
+        assert!(out_str.contains("diff --git a/run.sh"));
+    }
+
+    #[test]
+    fn test_path_filter_double_star() {
+        // '*' doesn't match across directories but '**' does
+        assert!(run_args(&["--match-fields", ALL_FIELDS, "--print-fields", ALL_FIELDS, "--path", "*.ts", "a"]).is_empty());
+        let out_str = run_args(&["--match-fields", ALL_FIELDS, "--print-fields", ALL_FIELDS, "--path", "**/*.ts", "a"]);
+        assert!(out_str.contains("diff --git a/src/game.ts"));
+        assert!(!out_str.contains("docs/notes.txt"));
+    }
+
+    #[test]
+    fn test_exclude_path() {
+        let out_str = run_args(&["--match-fields", ALL_FIELDS, "--print-fields", ALL_FIELDS, "--exclude-path", "src/**", "a"]);
+        assert!(!out_str.contains("src/game.ts"));
+        assert!(out_str.contains("diff --git a/docs/notes.txt"));
+        let out_str = run_args(&["--match-fields", ALL_FIELDS, "--print-fields", ALL_FIELDS, "--path", "**/*.t?", "--exclude-path", "docs", "a"]);
+        assert!(out_str.contains("diff --git a/src/game.ts"));
+        assert!(!out_str.contains("docs/notes.txt"));
+    }
+
+    #[test]
+    fn test_parse_file_paths() {
+        let paths = |lines: &[&str]| -> FilePaths {
+            parse_file_paths(&lines.iter().map(|line| line.to_string()).collect::<Vec<String>>())
+        };
+        let some = |path: &str| Some(path.to_string());
+        assert_eq!(
+            paths(&["diff --git a/src/a b.rs b/src/a b.rs", "old mode 100644", "new mode 100755"]),
+            FilePaths { old: some("src/a b.rs"), new: some("src/a b.rs") }
+        );
+        assert_eq!(
+            paths(&["diff --git a/x b/x", "new file mode 100644", "--- /dev/null", "+++ b/x"]),
+            FilePaths { old: None, new: some("x") }
+        );
+        assert_eq!(
+            paths(&["diff --git a/x b/x", "deleted file mode 100644", "index e69de29..0000000"]),
+            FilePaths { old: some("x"), new: None }
+        );
+        assert_eq!(
+            paths(&["diff --git a/old b/new", "similarity index 100%", "rename from old", "rename to new"]),
+            FilePaths { old: some("old"), new: some("new") }
+        );
+        assert_eq!(
+            paths(&["diff --git \"a/caf\\303\\251\" \"b/caf\\303\\251\"", "--- \"a/caf\\303\\251\"", "+++ \"b/caf\\303\\251\""]),
+            FilePaths { old: some("café"), new: some("café") }
+        );
+    }
+
+    #[test]
+    fn test_parse_file_paths_combined() {
+        let paths = |lines: &[&str]| -> FilePaths {
+            parse_file_paths(&lines.iter().map(|line| line.to_string()).collect::<Vec<String>>())
+        };
+        let some = |path: &str| Some(path.to_string());
+        assert_eq!(
+            paths(&["diff --cc src/a b.rs", "index c77265a,65b43eb..aba1d1f"]),
+            FilePaths { old: some("src/a b.rs"), new: some("src/a b.rs") }
+        );
+        assert_eq!(
+            paths(&["diff --combined x", "index c77265a,65b43eb..aba1d1f", "--- a/x", "+++ b/x"]),
+            FilePaths { old: some("x"), new: some("x") }
+        );
+    }
+
+    #[test]
+    fn test_parse_file_paths_diff_u() {
+        let paths = |lines: &[&str]| -> FilePaths {
+            parse_file_paths(&lines.iter().map(|line| line.to_string()).collect::<Vec<String>>())
+        };
+        let some = |path: &str| Some(path.to_string());
+        assert_eq!(
+            paths(&["--- old/a.txt\t2024-01-01 10:00:00.000000000 +0100", "+++ new/a.txt\t2024-01-02 10:00:00.000000000 +0100"]),
+            FilePaths { old: some("a.txt"), new: some("a.txt") }
+        );
+        // `diff -N` gives files that don't exist the epoch as their timestamp
+        assert_eq!(
+            paths(&["diff -ruN old/a new/a", "--- old/a\t1970-01-01 01:00:00.000000000 +0100", "+++ new/a\t2024-01-02 10:00:00.000000000 +0100"]),
+            FilePaths { old: None, new: some("a") }
+        );
+        assert_eq!(
+            paths(&["--- old/a\t2024-01-02 10:00:00.000000000 -0500", "+++ new/a\t1969-12-31 19:00:00.000000000 -0500"]),
+            FilePaths { old: some("a"), new: None }
+        );
+        assert_eq!(
+            paths(&["--- old/a\t1970-01-01 00:00:01.000000000 +0000", "+++ new/a\t1970-01-01 00:00:00.500000000 +0000"]),
+            FilePaths { old: some("a"), new: some("a") }
+        );
+        assert_eq!(
+            paths(&["Binary files old/assets/board.png and new/assets/board.png differ"]),
+            FilePaths { old: some("assets/board.png"), new: some("assets/board.png") }
+        );
+        // git writes the line as well, after the `diff --git` line
+        assert_eq!(
+            paths(&["diff --git a/x b/x", "new file mode 100644", "index 0000000..e69de29", "Binary files /dev/null and b/x differ"]),
+            FilePaths { old: None, new: some("x") }
+        );
+    }
+
+    #[test]
+    fn test_diff_r_binary_files_and_notes() {
+        let run = |path: &str| -> (Vec<String>, String) {
+            let file = fs::File::open(path).unwrap();
+            let mut config = Config::default();
+            config.search_string = "AIPlayer".to_string();
+            config.match_on.added = true;
+            config.match_on.removed = true;
+            config.output = OutputConfig::JsonLines;
+            let mut json_vec: Vec<u8> = Vec::new();
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut json_vec),
+                &config
+            ).unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&json_vec).unwrap();
+            let paths = json["files"].as_array().unwrap().iter()
+                .map(|file| file["new_path"].as_str().unwrap().to_string())
+                .collect();
+            let file = fs::File::open(path).unwrap();
+            config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+            let mut out_vec: Vec<u8> = Vec::new();
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            (paths, String::from_utf8(out_vec).unwrap())
+        };
+        // `Binary files` lines are files without hunks, even the first line
+        // or one straight after a hunk
+        let (paths, out_str) = run("test_data/diff_ruN_binary.diff");
+        assert_eq!(paths, ["assets/ai.png", "assets/board.png", "docs/notes.txt", "rules.txt", "src/game.ts", "start.wav"]);
+        assert!(out_str.starts_with("Binary files old/assets/ai.png and new/assets/ai.png differ\n"));
+        // `Only in` and `Common subdirectories` lines belong to no file
+        let (paths, out_str) = run("test_data/diff_ru_only_in.diff");
+        assert_eq!(paths, ["assets/board.png", "rules.txt", "src/game.ts"]);
+        assert!(!out_str.contains("Only in"));
+        let (paths, out_str) = run("test_data/diff_u_dirs.diff");
+        assert_eq!(paths, ["rules.txt"]);
+        assert!(!out_str.contains("Common subdirectories"));
+    }
+
+    #[test]
+    fn test_diff_ru_n() {
+        let file = fs::File::open("test_data/diff_ruN.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::FilesWithMatches;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "CHANGELOG.md\nlist.md\nnotes.txt\nsrc/game.ts\n");
+    }
+
+    #[test]
+    fn test_diff_u_without_diff_lines() {
+        let file = fs::File::open("test_data/diff_u.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "-".to_string();
+        config.pattern_kind = PatternKind::Regex;
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Lines;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // A removed line starting with "--" is only a new file once the hunk
+        // has all of its lines
+        assert_eq!(out_str, "list.md:2:-- second\n");
+    }
+
+    #[test]
+    fn test_diff_u_index_lines() {
+        let input = "\
+Index: project/a.txt
+===================================================================
+--- project.orig/a.txt
++++ project/a.txt
+@@ -1 +1 @@
+-old
++new
+Index: project/b.txt
+===================================================================
+--- project.orig/b.txt
++++ project/b.txt
+@@ -1 +1 @@
+-old b
++new b
+";
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "new".to_string();
+        config.match_on.added = true;
+        config.output = OutputConfig::Lines;
+        process_lines(
+            Box::new(input.as_bytes()),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        assert_eq!(out_str, "a.txt:1:new\nb.txt:1:new b\n");
+    }
+
+    #[test]
+    fn test_invert_match_commit_hash() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.invert_match = true;
+        config.output = OutputConfig::CommitHash;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+1a1923b7fbf20f2ba66b623ced035ae6916035e0
+b817d856b5de6f10d71f6c277f31ca82502c2be0
+187f3e205def6208cc394f5832329ad7690d2713
+");
+    }
+
+    #[test]
+    fn test_invert_match_hunk() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.invert_match = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        config.granularity = Granularity::Hunk;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("game started"));
+        assert!(out_str.contains("game finished"));
+        assert!(!out_str.contains("AIPlayer"));
+        assert!(!out_str.contains("docs/notes.txt"));
+    }
+
+    #[test]
+    fn test_invert_match_print_patch() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.invert_match = true;
+        config.output = OutputConfig::Patch;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains("@@ -14,6 +14,7 @@ export class Game {\n"));
+        assert!(out_str.contains("@@ -21,5 +22,6 @@ export class Game {\n"));
+        assert!(!out_str.contains("AIPlayer"));
+    }
+
+    #[test]
+    fn test_highlight() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.highlight = true;
+        config.color = ColorChoice::Always;
+        config.output = OutputConfig::Sections(PatchSections { added: true, ..PATCH_SECTIONS_NONE });
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.starts_with("\
++The \x1b[1;31mAIPlayer\x1b[m always plays black.
++import { \x1b[1;31mAIPlayer\x1b[m } from \"./ai\";
+"));
+        assert!(out_str.contains("+        this.board.log(\"game started\");\n"));
+    }
+
+    #[test]
+    fn test_highlight_color_never() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.highlight = true;
+        config.color = ColorChoice::Never;
+        config.output = OutputConfig::Sections(PatchSections { added: true, ..PATCH_SECTIONS_NONE });
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        assert!(out_str.starts_with("+The AIPlayer always plays black.\n"));
+    }
+
+    #[test]
+    fn test_highlight_keeps_git_colors() {
+        let file = fs::File::open("test_data/1_color.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        config.match_on.added = true;
+        config.highlight = true;
+        config.color = ColorChoice::Always;
+        config.output = OutputConfig::Sections(PatchSections { added: true, ..PATCH_SECTIONS_NONE });
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert!(out_str.contains(
+            "\x1b[32m+\x1b[m\x1b[32m        const rankMult: -1 | 1 = this.state.\x1b[1;31mplayer\x1b[m\x1b[32m === Player.White ? 1 : -1;\x1b[m\n"
+        ));
+    }
+
+    #[test]
+    fn test_highlight_line_across_colors() {
+        let find = |text: &str| -> Vec<std::ops::Range<usize>> {
+            text.find("ab").map(|start| start..start + 2).into_iter().collect()
+        };
+        // A color change inside a match is overridden by the highlight
+        assert_eq!(
+            highlight_line("\x1b[32mxa\x1b[1mby\x1b[m\n", &find),
+            "\x1b[32mx\x1b[1;31ma\x1b[1m\x1b[1;31mb\x1b[m\x1b[32m\x1b[1my\x1b[m\n"
+        );
+        assert_eq!(highlight_line("no match\n", &find), "no match\n");
+        assert_eq!(highlight_line("xaby\n", &find), "x\x1b[1;31mab\x1b[my\n");
+        // An escape character without a sequence after it is kept as text
+        assert_eq!(highlight_line("ab\x1b", &find), "\x1b[1;31mab\x1b[m\x1b");
+    }
+
+    #[test]
+    fn test_print_json() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.granularity = Granularity::Hunk;
+        config.output = OutputConfig::Json;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        let json: serde_json::Value = serde_json::from_str(&out_str).unwrap();
+        let patches = json.as_array().unwrap();
+        assert_eq!(patches.len(), 1);
+        let patch = &patches[0];
+        assert_eq!(patch["commit"], "c61b59de6e675356a2d492b9d402ff99875a9e02");
+        assert_eq!(patch["author"], "Alice Example <alice@example.com>");
+        assert_eq!(patch["date"], "Mon Jan 15 10:00:00 2024 +0100");
+        assert_eq!(patch["message"], "\
+Add AIPlayer as the black player
+
+The AI always plays black for now.
+
+Signed-off-by: Alice Example <alice@example.com>");
+        assert_eq!(patch["trailers"], serde_json::json!([
+            { "key": "Signed-off-by", "value": "Alice Example <alice@example.com>" }
+        ]));
+        // Only `--format=fuller` shows the committer
+        assert!(patch.get("committer").is_none());
+        let files = patch["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1]["old_path"], "src/game.ts");
+        assert_eq!(files[1]["new_path"], "src/game.ts");
+        let hunk = &files[1]["hunks"][0];
+        assert_eq!(hunk["old_start"], 1);
+        assert_eq!(hunk["old_lines"], 12);
+        assert_eq!(hunk["new_start"], 1);
+        assert_eq!(hunk["new_lines"], 13);
+        let lines = hunk["lines"].as_array().unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["kind"], "added");
+        assert_eq!(lines[1]["old_line"], serde_json::Value::Null);
+        assert_eq!(lines[1]["new_line"], 8);
+        assert_eq!(lines[1]["content"], "    black: AIPlayer;");
+    }
+
+    #[test]
+    fn test_print_json_no_matches() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "no such string".to_string();
+        config.match_on.added = true;
+        config.output = OutputConfig::Json;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&out_str).unwrap();
+        assert_eq!(json, serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_print_json_lines() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.match_on.context = true;
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.granularity = Granularity::Hunk;
+        config.output = OutputConfig::JsonLines;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        let patches: Vec<serde_json::Value> = out_str.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let commits: Vec<&str> = patches.iter().map(|patch| patch["commit"].as_str().unwrap()).collect();
+        assert_eq!(commits, [
+            "1a1923b7fbf20f2ba66b623ced035ae6916035e0",
+            "c61b59de6e675356a2d492b9d402ff99875a9e02",
+            "b817d856b5de6f10d71f6c277f31ca82502c2be0",
+            "187f3e205def6208cc394f5832329ad7690d2713",
+        ]);
+        // Context lines have both line numbers
+        let context_line = patches[2]["files"].as_array().unwrap().iter()
+            .flat_map(|file| file["hunks"].as_array().unwrap())
+            .flat_map(|hunk| hunk["lines"].as_array().unwrap())
+            .find(|line| line["kind"] == "context")
+            .unwrap();
+        assert!(context_line["old_line"].is_u64());
+        assert!(context_line["new_line"].is_u64());
+    }
+
+    #[test]
+    fn test_print_lines() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "black".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Lines;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+1a1923b7fbf20f2ba66b623ced035ae6916035e0:src/game.ts:7:    black: Player;
+1a1923b7fbf20f2ba66b623ced035ae6916035e0:src/game.ts:9:    constructor(white: Player, black: Player) {
+1a1923b7fbf20f2ba66b623ced035ae6916035e0:src/game.ts:12:        this.black = black;
+1a1923b7fbf20f2ba66b623ced035ae6916035e0:src/game.ts:18:        this.black.notify(\"start\");
+1a1923b7fbf20f2ba66b623ced035ae6916035e0:src/game.ts:23:        this.black.notify(\"finish\");
+c61b59de6e675356a2d492b9d402ff99875a9e02:docs/notes.txt:5:The AIPlayer always plays black.
+c61b59de6e675356a2d492b9d402ff99875a9e02:src/game.ts:7:    black: Player;
+c61b59de6e675356a2d492b9d402ff99875a9e02:src/game.ts:8:    black: AIPlayer;
+c61b59de6e675356a2d492b9d402ff99875a9e02:src/game.ts:9:    constructor(white: Player, black: Player) {
+c61b59de6e675356a2d492b9d402ff99875a9e02:src/game.ts:10:    constructor(white: Player, black: AIPlayer) {
+");
+    }
+
+    #[test]
+    fn test_print_lines_combined_diff() {
+        let file = fs::File::open("test_data/merge_combined.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "Player()".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Lines;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // A line removed from only the second parent is numbered in it
+        assert_eq!(out_str, "\
+28a1ca610066490465201f9f3bccefdffa37a718:src/game.ts:5:        this.player = new HumanPlayer();
+28a1ca610066490465201f9f3bccefdffa37a718:src/game.ts:5:        this.player = new AIPlayer();
+28a1ca610066490465201f9f3bccefdffa37a718:src/game.ts:5:        this.player = this.options.ai ? new AIPlayer() : new HumanPlayer();
+62a7acbe70544a421e9a2456982c0658919f73c0:src/game.ts:5:        this.player = new HumanPlayer();
+3389f8c5887f6050dd034ee2b3d7922324abbf44:src/game.ts:5:        this.player = new AIPlayer();
+");
+    }
+
+    #[test]
+    fn test_count_combined_diff() {
+        let file = fs::File::open("test_data/merge_combined.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.match_on.context = true;
+        config.granularity = Granularity::File;
+        config.output = OutputConfig::Count;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // Lines with a '+' or '-' in only the second column are added or
+        // removed too
+        assert!(out_str.starts_with("\
+28a1ca610066490465201f9f3bccefdffa37a718\tREADME.md\t3\t1\t4
+28a1ca610066490465201f9f3bccefdffa37a718\tsrc/game.ts\t1\t2\t6
+"));
+    }
+
+    #[test]
+    fn test_combined_diff_color() {
+        let file = fs::File::open("test_data/merge_cc_color.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = r"^ {8}this\.player = new".to_string();
+        config.pattern_kind = PatternKind::Regex;
+        config.match_on.removed = true;
+        config.granularity = Granularity::Hunk;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // Anchored patterns don't see the second column, but the indentation
+        // still has to match
+        assert!(!out_str.contains("README.md"));
+        assert!(out_str.contains("@@@ -2,6 -2,6 +2,6 @@@"));
+        assert!(out_str.contains("new HumanPlayer();"));
+    }
+
+    #[test]
+    fn test_print_lines_invert_match() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.invert_match = true;
+        config.granularity = Granularity::Hunk;
+        config.output = OutputConfig::Lines;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // Without a commit header there is no commit to print
+        assert_eq!(out_str, "\
+src/game.ts:18:        this.board.log(\"game started\");
+src/game.ts:26:        this.board.log(\"game finished\");
+");
+    }
+
+    #[test]
+    fn test_quickfix() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Quickfix;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+docs/notes.txt:5:5: c61b59de6e675356a2d492b9d402ff99875a9e02 The AIPlayer always plays black.
+src/game.ts:3:10: c61b59de6e675356a2d492b9d402ff99875a9e02 import { AIPlayer } from \"./ai\";
+src/game.ts:8:12: c61b59de6e675356a2d492b9d402ff99875a9e02 black: AIPlayer;
+src/game.ts:10:39: c61b59de6e675356a2d492b9d402ff99875a9e02 constructor(white: Player, black: AIPlayer) {
+");
+    }
+
+    #[test]
+    fn test_quickfix_invert_match() {
+        let file = fs::File::open("test_data/no_commit_header.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.invert_match = true;
+        config.granularity = Granularity::Hunk;
+        config.output = OutputConfig::Quickfix;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+src/game.ts:18:1: this.board.log(\"game started\");
+src/game.ts:26:1: this.board.log(\"game finished\");
+");
+    }
+
+    #[test]
+    fn test_occurrence_count_selects_lines() {
+        // The number of "black"s in src/game.ts didn't change, so none of its
+        // lines matched even though they contain it
+        assert_eq!(run_args(&["-S", "--print-lines", "black"]), "docs/notes.txt:5:The AIPlayer always plays black.\n");
+        assert_eq!(run_args(&["-S", "-c", "black"]), "1\t0\t0\n");
+        let json: serde_json::Value = serde_json::from_str(&run_args(&["-S", "--print-json", "black"])).unwrap();
+        let lines: Vec<&serde_json::Value> = json[0]["files"].as_array().unwrap().iter()
+            .flat_map(|file| file["hunks"].as_array().unwrap())
+            .flat_map(|hunk| hunk["lines"].as_array().unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["content"], "The AIPlayer always plays black.");
+    }
+
+    #[test]
+    fn test_scope_selects_lines() {
+        // Only the first hunk of src/game.ts has both patterns
+        let args = ["--scope", "hunk", "-e", "AIPlayer", "--and", "-e", "constructor"];
+        assert_eq!(run_args(&[&args[..], &["--print-lines"]].concat()), "\
+src/game.ts:3:import { AIPlayer } from \"./ai\";
+src/game.ts:8:    black: AIPlayer;
+src/game.ts:9:    constructor(white: Player, black: Player) {
+src/game.ts:10:    constructor(white: Player, black: AIPlayer) {
+");
+        assert_eq!(run_args(&[&args[..], &["-c"]].concat()), "3\t1\t0\n");
+        let json: serde_json::Value = serde_json::from_str(&run_args(&[&args[..], &["--print-json"]].concat())).unwrap();
+        let hunks: Vec<&serde_json::Value> = json[0]["files"].as_array().unwrap().iter()
+            .flat_map(|file| file["hunks"].as_array().unwrap())
+            .filter(|hunk| !hunk["lines"].as_array().unwrap().is_empty())
+            .collect();
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0]["new_start"], 1);
+        assert_eq!(hunks[0]["lines"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_count() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.output = OutputConfig::Count;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+1a1923b7fbf20f2ba66b623ced035ae6916035e0\t6\t0\t0
+c61b59de6e675356a2d492b9d402ff99875a9e02\t4\t2\t0
+");
+    }
+
+    #[test]
+    fn test_count_per_file() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.match_on.context = true;
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.granularity = Granularity::File;
+        config.output = OutputConfig::Count;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+1a1923b7fbf20f2ba66b623ced035ae6916035e0\tdocs/notes.txt\t1\t0\t0
+1a1923b7fbf20f2ba66b623ced035ae6916035e0\tsrc/game.ts\t4\t0\t0
+1a1923b7fbf20f2ba66b623ced035ae6916035e0\tsrc/player.ts\t1\t0\t0
+c61b59de6e675356a2d492b9d402ff99875a9e02\tdocs/notes.txt\t1\t0\t1
+c61b59de6e675356a2d492b9d402ff99875a9e02\tsrc/game.ts\t3\t2\t2
+b817d856b5de6f10d71f6c277f31ca82502c2be0\tdocs/notes.txt\t0\t0\t2
+187f3e205def6208cc394f5832329ad7690d2713\tsrc/player.ts\t0\t0\t1
+");
+    }
+
+    #[test]
+    fn test_merge_parents_grouped() {
+        let file = fs::File::open("test_data/merge_m.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "Player()".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::CommitHash;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // The merge is printed once, without its `(from <parent>)`
+        assert_eq!(out_str, "\
+28a1ca610066490465201f9f3bccefdffa37a718
+62a7acbe70544a421e9a2456982c0658919f73c0
+3389f8c5887f6050dd034ee2b3d7922324abbf44
+");
+    }
+
+    #[test]
+    fn test_merge_first_parent() {
+        let run = |first_parent: bool| -> String {
+            let file = fs::File::open("test_data/merge_m.diff").unwrap();
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = "Play a game".to_string();
+            config.match_on.removed = true;
+            config.first_parent = first_parent;
+            config.output = OutputConfig::FilesWithMatches;
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            String::from_utf8(out_vec).unwrap()
+        };
+        // Only the diff against the second parent removes the line
+        assert_eq!(run(false), "\
+28a1ca610066490465201f9f3bccefdffa37a718\tREADME.md
+62a7acbe70544a421e9a2456982c0658919f73c0\tREADME.md
+");
+        assert_eq!(run(true), "62a7acbe70544a421e9a2456982c0658919f73c0\tREADME.md\n");
+    }
+
+    #[test]
+    fn test_merge_parent_headers() {
+        let file = fs::File::open("test_data/merge_m.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "this.options.ai".to_string();
+        config.match_on.added = true;
+        config.granularity = Granularity::File;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // Each parent's diff follows its own header
+        assert!(out_str.starts_with("commit 28a1ca610066490465201f9f3bccefdffa37a718 (from 62a7acbe70544a421e9a2456982c0658919f73c0)\n"));
+        let second_header = out_str.find("commit 28a1ca610066490465201f9f3bccefdffa37a718 (from 3389f8c5887f6050dd034ee2b3d7922324abbf44)\n").unwrap();
+        assert_eq!(out_str.matches("diff --git a/src/game.ts").count(), 2);
+        assert!(out_str[second_header..].contains("-        this.player = new AIPlayer();"));
+        assert!(!out_str.contains("README.md"));
+    }
+
+    #[test]
+    fn test_merge_without_diff() {
+        let input = "\
+commit 28a1ca610066490465201f9f3bccefdffa37a718
+Merge: 62a7acb 3389f8c
+Author: Ada Lovelace <ada@example.com>
+
+    Merge branch 'ai'
+
+commit 3389f8c5887f6050dd034ee2b3d7922324abbf44
+Author: Ada Lovelace <ada@example.com>
+
+    Let the AI player start
+
+diff --git a/src/game.ts b/src/game.ts
+--- a/src/game.ts
++++ b/src/game.ts
+@@ -4 +4,2 @@ export class Game {
+         this.turn = 0;
++        this.player = new AIPlayer();
+";
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "Ada Lovelace".to_string();
+        config.match_on.patch_header = true;
+        config.output = OutputConfig::CommitHash;
+        process_lines(
+            Box::new(input.as_bytes()),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        assert_eq!(out_str, "28a1ca610066490465201f9f3bccefdffa37a718\n3389f8c5887f6050dd034ee2b3d7922324abbf44\n");
+    }
+
+    #[test]
+    fn test_show_refs() {
+        let run = |output: OutputConfig| -> String {
+            let file = fs::File::open("test_data/decorate.diff").unwrap();
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = "Player".to_string();
+            config.match_on.added = true;
+            config.show_refs = true;
+            config.output = output;
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            String::from_utf8(out_vec).unwrap()
+        };
+        // The hashes are printed without their decorations, and commits
+        // without refs have an empty column
+        assert_eq!(run(OutputConfig::CommitHash), "\
+62a7acbe70544a421e9a2456982c0658919f73c0\t
+3389f8c5887f6050dd034ee2b3d7922324abbf44\ttag: v0.1, feature/ai, ai
+87eed4c75a8e190309bac49554768745c0511bc9\t
+");
+        assert_eq!(run(OutputConfig::FilesWithMatches), "\
+62a7acbe70544a421e9a2456982c0658919f73c0\t\tsrc/game.ts
+3389f8c5887f6050dd034ee2b3d7922324abbf44\ttag: v0.1, feature/ai, ai\tsrc/game.ts
+87eed4c75a8e190309bac49554768745c0511bc9\t\tREADME.md
+");
+    }
+
+    #[test]
+    fn test_ref_filter() {
+        let run = |refs: &[&str], tags: &[&str]| -> String {
+            let file = fs::File::open("test_data/decorate.diff").unwrap();
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = "".to_string();
+            config.match_on.patch_header = true;
+            config.refs = refs.iter().map(|glob| glob.to_string()).collect();
+            config.tags = tags.iter().map(|glob| glob.to_string()).collect();
+            config.output = OutputConfig::CommitHash;
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            String::from_utf8(out_vec).unwrap()
+        };
+        let merge = "28a1ca610066490465201f9f3bccefdffa37a718\n";
+        let ai = "3389f8c5887f6050dd034ee2b3d7922324abbf44\n";
+        assert_eq!(run(&["main"], &[]), merge);
+        assert_eq!(run(&["HEAD"], &[]), merge);
+        assert_eq!(run(&["feature/*"], &[]), ai);
+        assert_eq!(run(&[], &["v*"]), format!("{}{}", merge, ai));
+        assert_eq!(run(&["main"], &["v0.1"]), format!("{}{}", merge, ai));
+        // --tag only matches tags, while --ref matches them too
+        assert_eq!(run(&[], &["ai"]), "");
+        assert_eq!(run(&["v0.1"], &[]), ai);
+    }
+
+    #[test]
+    fn test_author_and_date_filters() {
+        let run = |configure: &dyn Fn(&mut Config)| -> String {
+            let file = fs::File::open("test_data/log.diff").unwrap();
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = "".to_string();
+            config.match_on.patch_header = true;
+            config.output = OutputConfig::CommitHash;
+            configure(&mut config);
+            process_lines(
+                Box::new(BufReader::new(file)),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            String::from_utf8(out_vec).unwrap()
+        };
+        let first = "1a1923b7fbf20f2ba66b623ced035ae6916035e0\n";
+        let second = "c61b59de6e675356a2d492b9d402ff99875a9e02\n";
+        let third = "b817d856b5de6f10d71f6c277f31ca82502c2be0\n";
+        let fourth = "187f3e205def6208cc394f5832329ad7690d2713\n";
+        assert_eq!(run(&|config| config.authors = vec!["^Bob".to_string()]), third);
+        assert_eq!(
+            run(&|config| config.authors = vec!["bob@".to_string(), "Nobody".to_string()]),
+            third
+        );
+        // Only `--format=fuller` has a committer
+        assert_eq!(run(&|config| config.committers = vec!["Alice".to_string()]), "");
+        // 2024-03-02 09:30:00 -0500 is 14:30 UTC
+        assert_eq!(run(&|config| config.since = Some(1709389800)), format!("{}{}{}", first, third, fourth));
+        assert_eq!(run(&|config| config.since = Some(1709389801)), format!("{}{}", first, fourth));
+        assert_eq!(run(&|config| config.until = Some(1709389800)), format!("{}{}", second, third));
+        assert_eq!(
+            run(&|config| {
+                config.authors = vec!["Alice".to_string()];
+                config.since = Some(1704067200);
+                config.until = Some(1735689599);
+            }),
+            format!("{}{}", second, fourth)
+        );
+    }
+
+    #[test]
+    fn test_committer_filter() {
+        let input = "\
+commit 0123abcd
+Author:     Alice Example <alice@example.com>
+AuthorDate: Mon Jan 15 10:00:00 2024 +0100
+Commit:     Bob Example <bob@example.com>
+CommitDate: Tue Jan 16 09:30:00 2024 +0100
+
+    Unwrap the board
+
+diff --git a/src/game.ts b/src/game.ts
+--- a/src/game.ts
++++ b/src/game.ts
+@@ -1 +1 @@
+-this.board!.reset();
++this.board.reset();
+";
+        let run = |configure: &dyn Fn(&mut Config)| -> bool {
+            let mut config = Config::default();
+            config.search_string = "board".to_string();
+            config.match_on.added = true;
+            config.quiet = true;
+            configure(&mut config);
+            process_lines(
+                Box::new(input.as_bytes()),
+                Box::new(io::sink()),
+                &config
+            ).unwrap()
+        };
+        assert!(run(&|config| config.committers = vec!["Bob".to_string()]));
+        assert!(!run(&|config| config.committers = vec!["Alice".to_string()]));
+        // The commit date is used rather than the author date
+        assert!(run(&|config| config.since = parse_date("2024-01-16").map(|date| date.timestamp)));
+        assert!(!run(&|config| config.until = parse_date("2024-01-16 08:00 +0100").map(|date| date.timestamp)));
+    }
+
+    #[test]
+    fn test_files_with_matches() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.output = OutputConfig::FilesWithMatches;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+1a1923b7fbf20f2ba66b623ced035ae6916035e0\tdocs/notes.txt
+1a1923b7fbf20f2ba66b623ced035ae6916035e0\tsrc/game.ts
+1a1923b7fbf20f2ba66b623ced035ae6916035e0\tsrc/player.ts
+c61b59de6e675356a2d492b9d402ff99875a9e02\tdocs/notes.txt
+c61b59de6e675356a2d492b9d402ff99875a9e02\tsrc/game.ts
+");
+    }
+
+    #[test]
+    fn test_files_with_matches_unique() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.output = OutputConfig::FilesWithMatches;
+        config.unique = true;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+docs/notes.txt
+src/game.ts
+src/player.ts
+");
+    }
+
+    #[test]
+    fn test_files_without_match() {
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.output = OutputConfig::FilesWithoutMatch;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+1a1923b7fbf20f2ba66b623ced035ae6916035e0\trun.sh
+c61b59de6e675356a2d492b9d402ff99875a9e02\trun.sh
+b817d856b5de6f10d71f6c277f31ca82502c2be0\tCHANGELOG.md
+b817d856b5de6f10d71f6c277f31ca82502c2be0\tdocs/notes.txt
+187f3e205def6208cc394f5832329ad7690d2713\tsrc/player.ts
+");
+    }
+
+    #[test]
+    fn test_process_lines_matched() {
+        let mut config = Config::default();
+        config.match_on.added = true;
+        config.search_string = "AIPlayer".to_string();
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let matched = process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(io::sink()),
+            &config
+        ).unwrap();
+        assert!(matched);
+
+        config.search_string = "no such string".to_string();
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let matched = process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(io::sink()),
+            &config
+        ).unwrap();
+        assert!(!matched);
+    }
+
+    #[test]
+    fn test_quiet() {
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "AIPlayer".to_string();
+        config.match_on.added = true;
+        config.quiet = true;
+        // Reading stops at the first match, so the invalid UTF-8 after it is
+        // never decoded
+        let mut input = fs::read("test_data/log.diff").unwrap();
+        input.extend_from_slice(b"commit 0000000000000000000000000000000000000000\n\xff\n");
+        let matched = process_lines(
+            Box::new(input.as_slice()),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        assert!(matched);
+        assert!(out_vec.is_empty());
+    }
+
+    #[test]
+    fn test_quiet_no_match() {
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "no such string".to_string();
+        config.match_on.added = true;
+        config.quiet = true;
+        config.output = OutputConfig::Json;
+        let file = fs::File::open("test_data/log.diff").unwrap();
+        let matched = process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        assert!(!matched);
+        assert!(out_vec.is_empty());
+    }
+
+    /// A writer whose reader has gone away, like a pipe to `head` that has
+    /// read all it wants
+    struct ClosedPipe;
+
+    impl io::Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_closed_pipe() {
+        for output in [OutputConfig::Lines, OutputConfig::Json, OutputConfig::JsonLines] {
+            let mut config = Config::default();
+            config.search_string = "AIPlayer".to_string();
+            config.match_on.added = true;
+            config.output = output;
+            let sources = input_sources(&["test_data/inputs".to_string()]).unwrap();
+            let err = process_inputs(
+                &mut sources.iter().map(InputSource::open),
+                true,
+                Box::new(ClosedPipe),
+                &config
+            ).expect_err("");
+            // The error is left as it is, without the name of the input, so
+            // that main can tell it apart
+            assert!(is_broken_pipe(&*err), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_invert_match_empty_input() {
+        for output in [OutputConfig::Sections(PATCH_SECTIONS_ALL), OutputConfig::Json, OutputConfig::Count] {
+            let mut out_vec: Vec<u8> = Vec::new();
+            let mut config = Config::default();
+            config.search_string = "foo".to_string();
+            config.match_on.added = true;
+            config.invert_match = true;
+            config.output = output;
+            let matched = process_lines(
+                Box::new("".as_bytes()),
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            assert!(!matched);
+            let out_str = String::from_utf8(out_vec).unwrap();
+            assert!(out_str.is_empty() || out_str == "[\n]\n", "{}", out_str);
+        }
+    }
+
+    #[test]
+    fn test_input_sources() {
+        let sources = input_sources(&["test_data/inputs".to_string(), "-".to_string()]).unwrap();
+        let paths: Vec<String> = sources.iter().map(|source| match source {
+            InputSource::Stdin => "-".to_string(),
+            InputSource::File(path) => path.display().to_string(),
+        }).collect();
+        // Only the *.patch files are read
+        assert_eq!(paths, ["test_data/inputs/1.patch", "test_data/inputs/2.patch", "-"]);
+    }
+
+    #[test]
+    fn test_process_inputs() {
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        config.match_on.added = true;
+        config.match_on.context = true;
+        config.case_sensitivity = CaseSensitivity::Insensitive;
+        config.output = OutputConfig::CommitHash;
+        let sources = input_sources(&["test_data/inputs".to_string()]).unwrap();
+        let matched = process_inputs(
+            &mut sources.iter().map(InputSource::open),
+            true,
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        assert!(matched);
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+test_data/inputs/1.patch:c61b59de6e675356a2d492b9d402ff99875a9e02
+test_data/inputs/2.patch:b817d856b5de6f10d71f6c277f31ca82502c2be0
+");
+    }
+
+    #[test]
+    fn test_process_inputs_sections() {
+        let run = |args: &[&str]| -> String {
+            let mut out_vec: Vec<u8> = Vec::new();
+            let config = parse_args(args).unwrap();
+            let sources = input_sources(&["test_data/inputs".to_string()]).unwrap();
+            process_inputs(
+                &mut sources.iter().map(InputSource::open),
+                true,
+                Box::new(&mut out_vec),
+                &config
+            ).unwrap();
+            let out_str = String::from_utf8(out_vec).unwrap();
+            println!("{}", out_str);
+            out_str
+        };
+        // The default output names each input once, before its first patch
+        let out_str = run(&["-i", "--match-fields", "diff,context", "player"]);
+        let names: Vec<&str> = out_str.lines().filter(|line| line.starts_with("==>")).collect();
+        assert_eq!(names, ["==> test_data/inputs/1.patch <==", "==> test_data/inputs/2.patch <=="]);
+        assert!(out_str.starts_with("==> test_data/inputs/1.patch <==\ncommit c61b59de6e675356a2d492b9d402ff99875a9e02\n"));
+        assert!(out_str.contains("==> test_data/inputs/2.patch <==\ncommit b817d856b5de6f10d71f6c277f31ca82502c2be0\n"));
+        // Quickfix output keeps the path first for editors to jump to
+        let out_str = run(&["--quickfix", "AIPlayer"]);
+        assert!(out_str.starts_with("docs/notes.txt:5:5: test_data/inputs/1.patch c61b59de6e675356a2d492b9d402ff99875a9e02 The AIPlayer"));
+    }
+
+    #[test]
+    fn test_process_inputs_error() {
+        let mut config = Config::default();
+        config.search_string = "player".to_string();
+        let sources = input_sources(&[
+            "test_data/inputs/1.patch".to_string(),
+            "test_data/inputs/README.txt".to_string(),
+        ]).unwrap();
+        let err = process_inputs(
+            &mut sources.iter().map(InputSource::open),
+            true,
+            Box::new(io::sink()),
+            &config
+        ).unwrap_err();
+        assert!(err.to_string().starts_with("test_data/inputs/README.txt: "));
+
+        let sources = input_sources(&["test_data/no_such_file".to_string()]).unwrap();
+        let err = process_inputs(
+            &mut sources.iter().map(InputSource::open),
+            false,
+            Box::new(io::sink()),
+            &config
+        ).unwrap_err();
+        assert!(err.to_string().starts_with("test_data/no_such_file: "));
+    }
+
+    #[test]
+    fn test_mbox_commit_hash() {
+        let file = fs::File::open("test_data/series.mbox").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "game".to_string();
+        config.match_on.added = true;
+        config.output = OutputConfig::CommitHash;
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        assert_eq!(out_str, "\
+c61b59de6e675356a2d492b9d402ff99875a9e02
+187f3e205def6208cc394f5832329ad7690d2713
+");
+    }
+
+    #[test]
+    fn test_format_patch_signature() {
+        let file = fs::File::open("test_data/format_patch/0002-Document-game-logging.patch").unwrap();
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "logged".to_string();
+        config.match_on.added = true;
+        config.match_on.removed = true;
+        config.output = OutputConfig::Sections(PATCH_SECTIONS_ALL);
+        process_lines(
+            Box::new(BufReader::new(file)),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        println!("{}", out_str);
+        // The `-- ` line after the last hunk is not a removed line
+        assert!(out_str.starts_with("From b817d856b5de6f10d71f6c277f31ca82502c2be0 Mon Sep 17 00:00:00 2001\n"));
+        assert!(out_str.ends_with("+Games are logged to the board.\n"));
+    }
+
+    #[test]
+    fn test_format_patch_removed_dashes() {
+        // A removed line that starts with "- " is only the signature once the
+        // hunk has ended
+        let input = "\
+From 0123456789abcdef0123456789abcdef01234567 Mon Sep 17 00:00:00 2001
+From: A <a@example.com>
+Subject: [PATCH] Remove a list item
+
+---
+ list.md | 1 -
+
+diff --git a/list.md b/list.md
+--- a/list.md
++++ b/list.md
+@@ -1,2 +1 @@
+ - kept
+-- 
+-- 
+2.39.5
+
+";
+        let mut out_vec: Vec<u8> = Vec::new();
+        let mut config = Config::default();
+        config.search_string = "- ".to_string();
+        config.match_on.removed = true;
+        config.output = OutputConfig::Lines;
+        process_lines(
+            Box::new(input.as_bytes()),
+            Box::new(&mut out_vec),
+            &config
+        ).unwrap();
+        let out_str = String::from_utf8(out_vec).unwrap();
+        assert_eq!(out_str, "0123456789abcdef0123456789abcdef01234567:list.md:2:- \n");
+    }
+
+    #[test]
+    fn test_parse_mbox_header() {
+        let lines: Vec<String> = [
+            "From 0123abcd Mon Sep 17 00:00:00 2001",
+            "From: Alice Example <alice@example.com>",
+            "Date: Mon, 15 Jan 2024 10:00:00 +0100",
+            "Subject: [PATCH 1/3] A subject that is long enough",
+            " to be folded",
+            "",
+            "Body",
+            "",
+            "Signed-off-by: Alice Example <alice@example.com>",
+            "---",
+            " file | 1 +",
+        ].iter().map(|line| line.to_string()).collect();
+        assert_eq!(parse_patch_header(&lines), PatchHeader {
+            commit: Some("0123abcd".to_string()),
+            parent: None,
+            refs: Vec::new(),
+            author: Some("Alice Example <alice@example.com>".to_string()),
+            date: Some("Mon, 15 Jan 2024 10:00:00 +0100".to_string()),
+            message: Some("A subject that is long enough to be folded\n\nBody\n\nSigned-off-by: Alice Example <alice@example.com>".to_string()),
+            trailers: vec![Trailer {
+                key: "Signed-off-by".to_string(),
+                value: "Alice Example <alice@example.com>".to_string(),
+            }],
+            ..PatchHeader::default()
+        });
+    }
+
+    #[test]
+    fn test_parse_patch_header() {
+        let lines: Vec<String> = [
+            "commit 0123abcd (HEAD -> main, origin/main)",
+            "Author: Alice Example <alice@example.com>",
+            "Date:   Mon Jan 15 10:00:00 2024 +0100",
+            "",
+            "    Subject line",
+            "    ",
+            "    Body",
+            "",
+        ].iter().map(|line| line.to_string()).collect();
+        assert_eq!(parse_patch_header(&lines), PatchHeader {
+            commit: Some("0123abcd".to_string()),
+            parent: None,
+            refs: vec![
+                CommitRef { name: "HEAD".to_string(), is_tag: false },
+                CommitRef { name: "main".to_string(), is_tag: false },
+                CommitRef { name: "origin/main".to_string(), is_tag: false },
+            ],
+            author: Some("Alice Example <alice@example.com>".to_string()),
+            date: Some("Mon Jan 15 10:00:00 2024 +0100".to_string()),
+            message: Some("Subject line\n\nBody".to_string()),
+            ..PatchHeader::default()
+        });
+        assert_eq!(parse_patch_header(&[]), PatchHeader::default());
+    }
+
+    #[test]
+    fn test_parse_patch_header_fuller() {
+        let lines: Vec<String> = [
+            "commit 0123abcd",
+            "Merge: 4567ef0 89abcde",
+            "Author:     Alice Example <alice@example.com>",
+            "AuthorDate: Mon Jan 15 10:00:00 2024 +0100",
+            "Commit:     Bob Example <bob@example.com>",
+            "CommitDate: Tue Jan 16 09:30:00 2024 +0100",
+            "",
+            "    Merge branch 'ai'",
+            "    ",
+            "    Co-authored-by: Carol Example",
+            "      <carol@example.com>",
+            "    Signed-off-by: Bob Example <bob@example.com>",
+            "",
+        ].iter().map(|line| line.to_string()).collect();
+        let trailer = |key: &str, value: &str| Trailer { key: key.to_string(), value: value.to_string() };
+        assert_eq!(parse_patch_header(&lines), PatchHeader {
+            commit: Some("0123abcd".to_string()),
+            merge_parents: vec!["4567ef0".to_string(), "89abcde".to_string()],
+            author: Some("Alice Example <alice@example.com>".to_string()),
+            date: Some("Mon Jan 15 10:00:00 2024 +0100".to_string()),
+            committer: Some("Bob Example <bob@example.com>".to_string()),
+            commit_date: Some("Tue Jan 16 09:30:00 2024 +0100".to_string()),
+            message: Some("\
+Merge branch 'ai'
+
+Co-authored-by: Carol Example
+  <carol@example.com>
+Signed-off-by: Bob Example <bob@example.com>".to_string()),
+            trailers: vec![
+                trailer("Co-authored-by", "Carol Example <carol@example.com>"),
+                trailer("Signed-off-by", "Bob Example <bob@example.com>"),
+            ],
+            ..PatchHeader::default()
+        });
+    }
+
+    #[test]
+    fn test_parse_trailers() {
+        let trailers = |message: &[&str]| -> Vec<Trailer> {
+            let lines: Vec<String> = ["commit 0123abcd", ""].iter()
+                .chain(message)
+                .map(|line| line.to_string())
+                .collect();
+            parse_patch_header(&lines).trailers
+        };
+        // The subject is never a trailer
+        assert!(trailers(&["    Fixes: a bug"]).is_empty());
+        // Every line of the last paragraph has to be a trailer
+        assert!(trailers(&["    Subject", "", "    Fixes: a bug", "    and more"]).is_empty());
+        assert!(trailers(&["    Subject", "", "    Not a trailer: spaces in the key"]).is_empty());
+        assert_eq!(
+            trailers(&["    Subject", "", "    Body", "", "    Fixes: #12"]),
+            vec![Trailer { key: "Fixes".to_string(), value: "#12".to_string() }]
+        );
+    }
+
+    #[test]
+    fn test_parse_commit_line() {
+        let branch = |name: &str| CommitRef { name: name.to_string(), is_tag: false };
+        let tag = |name: &str| CommitRef { name: name.to_string(), is_tag: true };
+        assert_eq!(
+            parse_commit_line("commit 0123abcd"),
+            Some(CommitLine { hash: "0123abcd", parent: None, refs: Vec::new() })
+        );
+        assert_eq!(
+            parse_commit_line("commit 0123abcd (from 4567ef01)"),
+            Some(CommitLine { hash: "0123abcd", parent: Some("4567ef01"), refs: Vec::new() })
+        );
+        assert_eq!(
+            parse_commit_line("commit 0123abcd (HEAD -> main, tag: v0.4.1, origin/main)"),
+            Some(CommitLine {
+                hash: "0123abcd",
+                parent: None,
+                refs: vec![branch("HEAD"), branch("main"), tag("v0.4.1"), branch("origin/main")],
+            })
+        );
+        // `git log -m --decorate=full`
+        assert_eq!(
+            parse_commit_line("commit 0123abcd (from 4567ef01) (HEAD -> refs/heads/main, tag: refs/tags/v1, refs/remotes/origin/main)"),
+            Some(CommitLine {
+                hash: "0123abcd",
+                parent: Some("4567ef01"),
+                refs: vec![branch("HEAD"), branch("main"), tag("v1"), branch("origin/main")],
+            })
+        );
+        assert_eq!(parse_commit_line("Author: Alice"), None);
+        assert_eq!(format_refs(&[branch("HEAD"), branch("main"), tag("v1")]), "HEAD, main, tag: v1");
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let timestamp = |date: &str| parse_date(date).map(|date| date.timestamp);
+        // The formats of `git log --date`
+        for date in [
+            "Mon Jan 15 10:00:00 2024 +0100",
+            "2024-01-15 10:00:00 +0100",
+            "2024-01-15T10:00:00+01:00",
+            "Mon, 15 Jan 2024 10:00:00 +0100",
+            "1705309200 +0100",
+            "Mon Jan 15 09:00:00 2024",
+            "2024-01-15T09:00:00Z",
+        ] {
+            assert_eq!(timestamp(date), Some(1705309200), "{}", date);
+        }
+        assert_eq!(parse_date("2024-01-15"), Some(Date { timestamp: 1705276800, has_time: false }));
+        assert_eq!(timestamp("1970-01-01"), Some(0));
+        assert_eq!(timestamp("Thu Feb 29 23:59:59 2024 -0500"), Some(1709269199));
+        assert_eq!(timestamp("1969-12-31 23:00:00 +0000"), Some(-3600));
+        // Digits on their own are a day rather than seconds since the epoch
+        assert_eq!(timestamp("20240115"), Some(1705276800));
+        assert_eq!(timestamp("20990101"), Some(4070908800));
+        assert_eq!(timestamp("@1705309200"), Some(1705309200));
+        assert_eq!(timestamp("@1705309200 +0100"), Some(1705309200));
+        assert_eq!(timestamp("1705309200"), None);
+        // Days past the end of the month
+        assert_eq!(timestamp("2024-02-29"), Some(1709164800));
+        assert_eq!(timestamp("2000-02-29"), Some(951782400));
+        for date in ["2024-02-30", "2023-02-29", "1900-02-29", "2024-04-31", "20240230", "Sat Feb 30 10:00:00 2024 +0000"] {
+            assert_eq!(parse_date(date), None, "{}", date);
+        }
+        for date in ["", "2 days ago", "2024-13-01", "Jan 2024", "2024-01-15 25:00"] {
+            assert_eq!(parse_date(date), None, "{}", date);
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let config = parse_args(&["asd"]).unwrap();
+        assert_eq!(config.search_string, "asd");
+    }
+
+    #[test]
+    fn test_parse_extra_positional() {
+        assert_eq!(parse_args(&["asd", "qwe"]).unwrap().inputs, vec!["qwe"]);
+    }
+
+    #[test]
+    fn test_parse_extra_positional_explicit() {
+        expect_err(parse_args(&["asd", "--", "qwe"]));
+    }
+
+    #[test]
+    fn test_parse_trailing_explicit() {
+        expect_err(parse_args(&["asd", "--"]));
+    }
+
+    #[test]
+    fn test_parse_explicit() {
+        let config = parse_args(&["--", "asd"]).unwrap();
+        assert!(config.search_string == "asd");
+    }
+
+    #[test]
+    fn test_parse_explicit_flag_like() {
+        let config = parse_args(&["--", "-h"]).unwrap();
+        assert!(config.search_string == "-h");
+    }
+    
+    #[test]
+    fn test_parse_explicit_duplicate_flag_like() {
+        let config = parse_args(&["--match-fields", "diff", "--", "-h"]).unwrap();
+        assert!(config.search_string == "-h");
+    }
+    
+    #[test]
+    fn test_parse_explicit_duplicate_flag_like_with_arg() {
+        let config = parse_args(&["--match-fields", "diff", "--", "--match-fields"]).unwrap();
+        assert!(config.search_string == "--match-fields");
+    }
+
+    #[test]
+    fn test_parse_no_program_name() {
+        expect_err(parse_args(&[]));
+    }
+
+    #[test]
+    fn test_parse_match_fields() {
+        let config = parse_args(&["asd", "--match-fields", "diff,context"]).unwrap();
+        assert_eq!(config.search_string, "asd");
+        assert!(config.match_on.added);
+        assert!(config.match_on.removed);
+        assert!(config.match_on.context);
+        assert!(!config.match_on.file_header);
+        assert!(!config.match_on.patch_header);
+    }
+
+    #[test]
+    fn test_parse_match_fields_added() {
+        let config = parse_args(&["asd", "--match-fields", "added", "--print-fields", "removed"]).unwrap();
+        assert!(config.match_on.added);
+        assert!(!config.match_on.removed);
+        match config.output {
+            OutputConfig::Sections(sections) => {
+                assert!(!sections.added);
+                assert!(sections.removed);
+            }
+            _ => panic!("Expected sections output"),
+        }
+    }
+
+    #[test]
+    fn test_parse_match_fields_repeat_positional_after() {
+        // A second positional argument is an input
+        assert_eq!(parse_args(&["asd", "--match-fields", "diff,context", "qwe"]).unwrap().inputs, vec!["qwe"]);
+    }
+
+    #[test]
+    fn test_parse_match_on_invalid() {
+        expect_err(parse_args(&["asd", "--match-on", "qwe"]));
+    }
+
+    #[test]
+    fn test_parse_print_fields_commit() {
+        expect_err(parse_args(&["asd", "--print-fields", "diff,context", "--print-commits"]));
+    }
+
+    #[test]
+    fn test_parse_commit_print_fields() {
+        expect_err(parse_args(&["asd", "--print-commits", "--print-fields", "diff,context"]));
+    }
+
+    #[test]
+    fn test_parse_utf8_invalid() {
+        expect_err(parse_args(&["asd", "--invalid-utf8", "qwe"]));
+    }
+
+    #[test]
+    fn test_parse_utf8_missing() {
+        expect_err(parse_args(&["asd", "--invalid-utf8"]));
+    }
+
+    #[test]
+    fn test_parse_utf8_valid() {
+        let config = parse_args(&["asd", "--invalid-utf8", "skip-line"]).unwrap();
+        assert_eq!(config.search_string, "asd");
+        assert_eq!(config.decode_strategy, UTF8Strategy::SkipLine);
+    }
+
+    #[test]
+    fn test_parse_granularity() {
+        let config = parse_args(&["asd", "--granularity", "hunk"]).unwrap();
+        assert_eq!(config.granularity, Granularity::Hunk);
+        let config = parse_args(&["asd"]).unwrap();
+        assert_eq!(config.granularity, Granularity::Patch);
+    }
+
+    #[test]
+    fn test_parse_granularity_invalid() {
+        expect_err(parse_args(&["asd", "--granularity", "line"]));
+        expect_err(parse_args(&["asd", "--granularity"]));
+    }
+
+    #[test]
+    fn test_parse_print_patch_commit() {
+        expect_err(parse_args(&["asd", "--print-patch", "--print-commits"]));
+    }
+
+    #[test]
+    fn test_parse_regex() {
+        let config = parse_args(&["--regex", "^fn [a-z_]+\\("]).unwrap();
+        assert_eq!(config.pattern_kind, PatternKind::Regex);
+        assert_eq!(config.search_string, "^fn [a-z_]+\\(");
+        let config = parse_args(&["asd"]).unwrap();
+        assert_eq!(config.pattern_kind, PatternKind::Fixed);
+    }
+
+    #[test]
+    fn test_parse_case_sensitivity() {
+        let config = parse_args(&["-i", "asd"]).unwrap();
+        assert_eq!(config.case_sensitivity, CaseSensitivity::Insensitive);
+        let config = parse_args(&["asd", "--ignore-case", "--smart-case"]).unwrap();
+        assert_eq!(config.case_sensitivity, CaseSensitivity::Smart);
+        let config = parse_args(&["asd"]).unwrap();
+        assert_eq!(config.case_sensitivity, CaseSensitivity::Sensitive);
+    }
+
+    #[test]
+    fn test_parse_pickaxe() {
+        let config = parse_args(&["-S", "asd"]).unwrap();
+        assert_eq!(config.pickaxe, Pickaxe::OccurrenceCount);
+        let config = parse_args(&["-S", "-G", "asd"]).unwrap();
+        assert_eq!(config.pickaxe, Pickaxe::LineMatch);
+    }
+
+    #[test]
+    fn test_parse_pattern_expr() {
+        let pattern = |s: &str| Box::new(PatternExpr::Pattern(s.to_string()));
+        let config = parse_args(&["-e", "a", "-e", "b"]).unwrap();
+        assert_eq!(config.pattern_expr, Some(PatternExpr::Or(pattern("a"), pattern("b"))));
+        let config = parse_args(&["-e", "a", "--and", "-e", "b", "--or", "-e", "c"]).unwrap();
+        assert_eq!(config.pattern_expr, Some(PatternExpr::Or(
+            Box::new(PatternExpr::And(pattern("a"), pattern("b"))),
+            pattern("c")
+        )));
+        let config = parse_args(&["-e", "a", "--or", "-e", "b", "--and", "--not", "-e", "-c"]).unwrap();
+        assert_eq!(config.pattern_expr, Some(PatternExpr::Or(
+            pattern("a"),
+            Box::new(PatternExpr::And(pattern("b"), Box::new(PatternExpr::Not(pattern("-c")))))
+        )));
+        let config = parse_args(&["asd"]).unwrap();
+        assert_eq!(config.pattern_expr, None);
+    }
+
+    #[test]
+    fn test_parse_pattern_expr_invalid() {
+        expect_err(parse_args(&["-e", "a", "--and"]));
+        expect_err(parse_args(&["--or", "-e", "a"]));
+        expect_err(parse_args(&["-e"]));
+        expect_err(parse_args(&["asd", "-e", "a"]));
+        expect_err(parse_args(&["--not"]));
+    }
+
+    #[test]
+    fn test_parse_scope() {
+        let config = parse_args(&["-e", "a", "--and", "-e", "b", "--scope", "hunk"]).unwrap();
+        assert_eq!(config.scope, MatchScope::Hunk);
+        expect_err(parse_args(&["-e", "a", "--scope", "commit"]));
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let config = parse_args(&["--path", "src", "asd", "--path", "*.md", "--exclude-path", "docs"]).unwrap();
+        assert_eq!(config.paths, vec!["src", "*.md"]);
+        assert_eq!(config.exclude_paths, vec!["docs"]);
+        expect_err(parse_args(&["asd", "--path"]));
+    }
+
+    #[test]
+    fn test_parse_invert_match() {
+        assert!(parse_args(&["-v", "asd"]).unwrap().invert_match);
+        assert!(parse_args(&["asd", "--invert-match"]).unwrap().invert_match);
+        assert!(!parse_args(&["asd"]).unwrap().invert_match);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_args(&["asd"]).unwrap().color, ColorChoice::Auto);
+        assert_eq!(parse_args(&["--color", "always", "asd"]).unwrap().color, ColorChoice::Always);
+        assert_eq!(parse_args(&["--color=never", "asd"]).unwrap().color, ColorChoice::Never);
+        assert!(parse_args(&["--highlight", "asd"]).unwrap().highlight);
+        assert!(parse_args(&["--color=sometimes", "asd"]).is_err());
+        assert!(parse_args(&["asd", "--color"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_print_json() {
+        assert!(matches!(parse_args(&["--print-json", "asd"]).unwrap().output, OutputConfig::Json));
+        assert!(matches!(parse_args(&["--print-jsonl", "asd"]).unwrap().output, OutputConfig::JsonLines));
+        assert!(parse_args(&["--print-json", "--print-commits", "asd"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_print_lines() {
+        assert!(matches!(parse_args(&["--print-lines", "asd"]).unwrap().output, OutputConfig::Lines));
+        assert!(parse_args(&["--print-lines", "--print-patch", "asd"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_quickfix() {
+        assert!(matches!(parse_args(&["--quickfix", "asd"]).unwrap().output, OutputConfig::Quickfix));
+        assert!(parse_args(&["--quickfix", "--print-lines", "asd"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert!(matches!(parse_args(&["-c", "asd"]).unwrap().output, OutputConfig::Count));
+        assert!(matches!(parse_args(&["--count", "asd"]).unwrap().output, OutputConfig::Count));
+        assert!(parse_args(&["--count", "--print-commits", "asd"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_files_with_matches() {
+        assert!(matches!(parse_args(&["-l", "asd"]).unwrap().output, OutputConfig::FilesWithMatches));
+        assert!(matches!(parse_args(&["--files-without-match", "asd"]).unwrap().output, OutputConfig::FilesWithoutMatch));
+        assert!(parse_args(&["-l", "--unique", "asd"]).unwrap().unique);
+        assert!(parse_args(&["-l", "--files-without-match", "asd"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_quiet() {
+        assert!(parse_args(&["-q", "asd"]).unwrap().quiet);
+        assert!(parse_args(&["asd", "--quiet"]).unwrap().quiet);
+        assert!(!parse_args(&["asd"]).unwrap().quiet);
+    }
+
+    #[test]
+    fn test_parse_first_parent() {
+        assert!(parse_args(&["--first-parent", "asd"]).unwrap().first_parent);
+        assert!(!parse_args(&["asd"]).unwrap().first_parent);
+    }
+
+    #[test]
+    fn test_parse_refs() {
+        let config = parse_args(&["--ref", "main", "asd", "--tag", "v*", "--ref", "origin/*", "--show-refs"]).unwrap();
+        assert_eq!(config.refs, vec!["main", "origin/*"]);
+        assert_eq!(config.tags, vec!["v*"]);
+        assert!(config.show_refs);
+        expect_err(parse_args(&["asd", "--tag"]));
+    }
+
+    #[test]
+    fn test_parse_author_and_dates() {
+        let config = parse_args(&["--author", "alice", "--committer", "bob", "asd", "--author", "carol"]).unwrap();
+        assert_eq!(config.authors, vec!["alice", "carol"]);
+        assert_eq!(config.committers, vec!["bob"]);
+        let config = parse_args(&["--since", "2024-01-15", "--until", "2024-01-15", "asd"]).unwrap();
+        assert_eq!(config.since, Some(1705276800));
+        // A day on its own includes all of it
+        assert_eq!(config.until, Some(1705276800 + 86399));
+        assert_eq!(parse_args(&["--until", "2024-01-15 10:00", "asd"]).unwrap().until, Some(1705312800));
+        assert_eq!(parse_args(&["--since", "20990101", "asd"]).unwrap().since, Some(4070908800));
+        expect_err(parse_args(&["--since", "yesterday", "asd"]));
+        expect_err(parse_args(&["--since", "2024-02-30", "asd"]));
+        expect_err(parse_args(&["asd", "--until"]));
+    }
+
+    #[test]
+    fn test_parse_inputs() {
+        assert_eq!(parse_args(&["asd", "a.patch", "-", "dir"]).unwrap().inputs, vec!["a.patch", "-", "dir"]);
+        assert_eq!(parse_args(&["--input", "a.patch", "asd", "b.patch"]).unwrap().inputs, vec!["a.patch", "b.patch"]);
+        assert_eq!(parse_args(&["-e", "asd", "a.patch"]).unwrap().inputs, vec!["a.patch"]);
+        assert_eq!(parse_args(&["--", "-asd", "-a.patch"]).unwrap().inputs, vec!["-a.patch"]);
+        assert!(parse_args(&["asd"]).unwrap().inputs.is_empty());
+        expect_err(parse_args(&["asd", "--unknown"]));
+        expect_err(parse_args(&["asd", "--input"]));
     }
 
     #[test]
     fn test_parse_help() {
-        expect_err(parse_args(&vec!["asd", "-h"]));
+        expect_err(parse_args(&["asd", "-h"]));
     }
 }